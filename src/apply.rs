@@ -0,0 +1,93 @@
+use crate::client::RmqClient;
+use crate::{Action, confirm_changes, execute};
+use serde::Deserialize;
+use std::path::Path;
+
+/// A step from the rules file, e.g.
+/// ```toml
+/// [[step]]
+/// name = "delete test queues"
+/// vhost = "dev"
+///
+/// [step.delete]
+/// queues = true
+/// queue-filter = "^test_"
+/// ```
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Step {
+    name: Option<String>,
+    vhost: Option<String>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Deserialize)]
+struct StepsFile {
+    #[serde(rename = "step")]
+    steps: Vec<Step>,
+}
+
+pub fn apply_steps(
+    rc: &RmqClient,
+    default_vhost: &str,
+    dry_run: bool,
+    yes: bool,
+    rules: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let StepsFile { steps } = toml::from_str(&std::fs::read_to_string(rules)?)?;
+    if steps.is_empty() {
+        return Err("Rules file doesn't contain any steps".into());
+    }
+
+    // the preview runs every step in dry run mode, so a step doesn't see changes made by the previous ones
+    println!("Plan:");
+    for (i, step) in steps.iter().enumerate() {
+        print_step(i, step, default_vhost);
+        execute(
+            rc,
+            vhost(step, default_vhost),
+            true,
+            true,
+            step.action.clone(),
+        )?;
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    if !confirm_changes(rc, &format!("Apply {} steps?", steps.len()), yes)? {
+        return Err("Aborted".into());
+    }
+
+    // the single confirmation above covers confirmation questions of all steps
+    for (i, step) in steps.iter().enumerate() {
+        print_step(i, step, default_vhost);
+        let vhost = vhost(step, default_vhost);
+        let rule = step
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("step {}", i + 1));
+        rc.metrics.observe(vhost, &rule, || {
+            execute(rc, vhost, false, true, step.action.clone())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn vhost<'a>(step: &'a Step, default_vhost: &'a str) -> &'a str {
+    step.vhost.as_deref().unwrap_or(default_vhost)
+}
+
+fn print_step(i: usize, step: &Step, default_vhost: &str) {
+    println!(
+        "\nStep {}{} on vhost '{}'",
+        i + 1,
+        step.name
+            .as_ref()
+            .map(|name| format!(" '{}'", name))
+            .unwrap_or_default(),
+        vhost(step, default_vhost)
+    );
+}
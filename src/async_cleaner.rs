@@ -0,0 +1,407 @@
+use crate::cleaner::{
+    ActionResult, CleanerBuilder, DeleteOrder, DeleteSelection, DeleteStep, ObjectResult, Outcome,
+    QueueSelection, exchange_result, policy_result, queue_result,
+};
+use crate::client::{Listing, NameRow, Page, QUEUE_COLUMNS, QueueRow};
+use crate::collector::{
+    CollectedObjects, ExchangeName, Queue, QuorumWait, Topology, TopologyRequest, select_objects,
+    select_queues_on_nodes, select_queues_with_messages, topology_requests,
+};
+use crate::management::Binding;
+use crate::metrics::Metrics;
+use crate::request::{RequestSettings, TransientError};
+use crate::safety::Limits;
+use futures_util::{StreamExt, stream};
+use rabbitmq_http_client::api::{Client, ClientBuilder};
+use rabbitmq_http_client::error::{Error, HttpClientError};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Variant of [`Cleaner`](crate::Cleaner) for async programs, built with [`CleanerBuilder::build_async`].
+/// Objects are selected the same way, requests are sent with the async client and waiting never blocks a thread
+pub struct AsyncCleaner<'a> {
+    client: Client<String, String, String>,
+    http: reqwest::Client,
+    endpoint: String,
+    username: String,
+    password: String,
+    vhost: String,
+    dry_run: bool,
+    concurrency: u16,
+    requests: RequestSettings,
+    wait_for_quorum: Duration,
+    limits: Limits,
+    keep_going: bool,
+    metrics: Option<Metrics>,
+    progress: Box<dyn Fn(&ObjectResult) + Send + Sync + 'a>,
+}
+
+impl<'a, P: Fn(&ObjectResult) + Send + Sync + 'a> CleanerBuilder<'a, P> {
+    /// Builds an [`AsyncCleaner`] with the same settings instead
+    pub fn build_async(self) -> Result<AsyncCleaner<'a>, Box<dyn std::error::Error>> {
+        let http = reqwest::Client::builder()
+            .timeout(self.request_timeout)
+            .build()?;
+        let metrics = self.metrics()?;
+
+        Ok(AsyncCleaner {
+            client: ClientBuilder::new()
+                .with_endpoint(self.endpoint.to_string())
+                .with_basic_auth_credentials(self.username.to_string(), self.password.to_string())
+                .with_client(http.clone())
+                .build(),
+            http,
+            endpoint: self.endpoint.to_string(),
+            username: self.username.to_string(),
+            password: self.password.to_string(),
+            vhost: self.vhost,
+            dry_run: self.dry_run,
+            concurrency: self.concurrency,
+            requests: RequestSettings::new(self.max_requests_per_second, self.retries),
+            wait_for_quorum: self.wait_for_quorum,
+            limits: self.limits,
+            keep_going: self.keep_going,
+            metrics,
+            progress: Box::new(self.progress),
+        })
+    }
+}
+
+impl AsyncCleaner<'_> {
+    pub fn vhost(&self) -> &str {
+        &self.vhost
+    }
+
+    /// Collects queues with messages to purge
+    pub async fn plan_purge(
+        &self,
+        selection: &QueueSelection,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        let queues = self.list_queues(Some(&selection.filter)).await?;
+        let queues = select_queues_with_messages(queues, &selection.filter, &selection.exclude)?;
+        select_queues_on_nodes(
+            queues,
+            selection.node.as_deref(),
+            selection.replica_node.as_deref(),
+        )
+    }
+
+    /// Purges the queues after checking them against the limits. Failed queues are in the result
+    pub async fn purge(
+        &self,
+        queues: &[Queue],
+    ) -> Result<ActionResult, Box<dyn std::error::Error>> {
+        self.limits.check(false, "purge", queues, 0)?;
+        let started = Instant::now();
+        let mut result = ActionResult {
+            dry_run: self.dry_run,
+            objects: vec![],
+        };
+        let purges = queues.iter().map(|queue| self.purge_queue(queue)).collect();
+        self.record_all(&mut result, purges).await;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&self.vhost, "purge", &result, started.elapsed())?;
+        }
+        Ok(result)
+    }
+
+    /// Collects queues, exchanges and policies to delete
+    pub async fn plan_delete(
+        &self,
+        selection: &DeleteSelection,
+    ) -> Result<CollectedObjects, Box<dyn std::error::Error>> {
+        let vhost = self.vhost.as_str();
+        let mut topology = Topology::default();
+        for request in topology_requests(&selection.options) {
+            match request {
+                TopologyRequest::Queues(name_filter) => {
+                    topology.queues = self.list_queues(name_filter).await?;
+                }
+                TopologyRequest::Exchanges => {
+                    let rows: Vec<NameRow> =
+                        self.list_paged(&["exchanges", vhost], "name", None).await?;
+                    topology.exchanges = rows.into_iter().map(|row| row.name).collect();
+                }
+                TopologyRequest::Bindings => {
+                    let bindings = self.request(|| self.client.list_bindings_in(vhost)).await?;
+                    topology.bindings = bindings
+                        .into_iter()
+                        .map(|binding| Binding {
+                            source: binding.source,
+                            destination: binding.destination,
+                            destination_type: binding.destination_type,
+                        })
+                        .collect();
+                }
+                TopologyRequest::Policies => {
+                    topology.policies =
+                        self.request(|| self.client.list_policies_in(vhost)).await?;
+                }
+                TopologyRequest::OperatorPolicies => {
+                    topology.operator_policies = self
+                        .request(|| self.client.list_operator_policies_in(vhost))
+                        .await?;
+                }
+            }
+        }
+
+        select_objects(topology, &selection.options)
+    }
+
+    /// Deletes the objects after checking them against the limits. Failed objects are in the result
+    pub async fn delete(
+        &self,
+        objects: &CollectedObjects,
+    ) -> Result<ActionResult, Box<dyn std::error::Error>> {
+        self.limits
+            .check(false, "delete", &objects.queues, objects.exchanges.len())?;
+        let started = Instant::now();
+        let result = self.delete_objects(objects).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&self.vhost, "delete", &result, started.elapsed())?;
+        }
+        Ok(result)
+    }
+
+    async fn delete_objects(&self, objects: &CollectedObjects) -> ActionResult {
+        let vhost = self.vhost.as_str();
+        let mut result = ActionResult {
+            dry_run: self.dry_run,
+            objects: vec![],
+        };
+
+        let mut order = DeleteOrder::new(objects, self.wait_for_quorum, self.keep_going);
+        while let Some(step) = order.next(&result) {
+            match step {
+                DeleteStep::Queues {
+                    queues,
+                    wait_for_quorum,
+                    quorum_deadline,
+                } => {
+                    let deletions = queues
+                        .iter()
+                        .map(|queue| self.delete_queue(queue, wait_for_quorum, quorum_deadline))
+                        .collect();
+                    self.record_all(&mut result, deletions).await;
+                }
+                DeleteStep::Exchanges(exchanges) => {
+                    let deletions = exchanges
+                        .iter()
+                        .map(|exchange| self.delete_exchange(exchange))
+                        .collect();
+                    self.record_all(&mut result, deletions).await;
+                }
+                DeleteStep::Policy(policy) => {
+                    let outcome = if policy.operator {
+                        self.act(|| {
+                            self.client
+                                .delete_operator_policy(vhost, &policy.name, true)
+                        })
+                        .await
+                    } else {
+                        self.act(|| self.client.delete_policy(vhost, &policy.name, true))
+                            .await
+                    };
+                    result.record(policy_result(policy, outcome), &self.progress);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Runs the actions up to `concurrency` at a time and records their results in order. After the first failure
+    /// no new actions are started unless `keep_going` is set, the running ones are finished and recorded too
+    async fn record_all(
+        &self,
+        result: &mut ActionResult,
+        actions: Vec<impl Future<Output = ObjectResult>>,
+    ) {
+        let stop = AtomicBool::new(false);
+        let actions: Vec<_> = actions
+            .into_iter()
+            .map(|action| unless_stopped(&stop, action))
+            .collect();
+        let mut outcomes = stream::iter(actions).buffered(self.concurrency.max(1) as usize);
+        while let Some(object) = outcomes.next().await {
+            let Some(object) = object else {
+                continue;
+            };
+            if !self.keep_going && matches!(object.outcome, Outcome::Failed(_)) {
+                stop.store(true, Ordering::SeqCst);
+            }
+            result.record(object, &self.progress);
+        }
+    }
+
+    async fn purge_queue(&self, queue: &Queue) -> ObjectResult {
+        if queue.is_stream() {
+            return queue_result(queue, Ok(Outcome::SkippedStream));
+        }
+        let outcome = self
+            .act_on_queue(queue, || self.client.purge_queue(&self.vhost, &queue.name))
+            .await;
+        queue_result(queue, outcome)
+    }
+
+    async fn delete_queue(
+        &self,
+        queue: &Queue,
+        wait_for_quorum: bool,
+        quorum_deadline: Option<Instant>,
+    ) -> ObjectResult {
+        if !queue.exclusive && wait_for_quorum {
+            match self.wait_for_quorum(queue, quorum_deadline).await {
+                Ok(Some(reason)) => {
+                    return queue_result(queue, Ok(Outcome::SkippedUnhealthy(reason)));
+                }
+                Ok(None) => {}
+                Err(e) => return queue_result(queue, Err(e.to_string())),
+            }
+        }
+        let outcome = self
+            .act_on_queue(queue, || {
+                self.client.delete_queue(&self.vhost, &queue.name, true)
+            })
+            .await;
+        queue_result(queue, outcome)
+    }
+
+    async fn delete_exchange(&self, exchange: &ExchangeName) -> ObjectResult {
+        let outcome = self
+            .act(|| self.client.delete_exchange(&self.vhost, exchange, true))
+            .await;
+        exchange_result(exchange, outcome)
+    }
+
+    /// Async counterpart of `collector::wait_for_quorum`
+    async fn wait_for_quorum(
+        &self,
+        queue: &Queue,
+        deadline: Option<Instant>,
+    ) -> Result<Option<String>, HttpClientError> {
+        let mut waiting = match QuorumWait::start(queue, self.dry_run, deadline) {
+            ControlFlow::Break(problem) => return Ok(problem),
+            ControlFlow::Continue(waiting) => waiting,
+        };
+        while let Some(poll) = waiting.next_poll() {
+            tokio::time::sleep_until(poll.into()).await;
+            let current = match self
+                .request(|| self.client.get_queue_info(&self.vhost, &queue.name))
+                .await
+            {
+                Ok(info) => Some(Queue::from(info)),
+                Err(Error::NotFound) => None,
+                Err(e) => return Err(e),
+            };
+            if let ControlFlow::Break(problem) = waiting.update(current) {
+                return Ok(problem);
+            }
+        }
+
+        Ok(waiting.problem())
+    }
+
+    /// Sends the request unless the queue is exclusive
+    async fn act_on_queue<F, E>(&self, queue: &Queue, f: impl Fn() -> F) -> Result<Outcome, String>
+    where
+        F: Future<Output = Result<(), E>>,
+        E: TransientError + std::fmt::Display,
+    {
+        if queue.exclusive {
+            return Ok(Outcome::SkippedExclusive);
+        }
+        self.act(f).await
+    }
+
+    /// Sends the request unless in dry run mode
+    async fn act<F, E>(&self, f: impl Fn() -> F) -> Result<Outcome, String>
+    where
+        F: Future<Output = Result<(), E>>,
+        E: TransientError + std::fmt::Display,
+    {
+        if !self.dry_run {
+            self.request(f).await.map_err(|e| e.to_string())?;
+        }
+        Ok(Outcome::Done)
+    }
+
+    /// Async counterpart of `RmqClient::request`, sharing its rate limit and backoff
+    async fn request<T, E, F>(&self, f: impl Fn() -> F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: TransientError,
+    {
+        let mut attempt = 0;
+        loop {
+            if let Some(start) = self.requests.reserve_turn() {
+                tokio::time::sleep_until(start.into()).await;
+            }
+
+            let result = f().await;
+            match result
+                .as_ref()
+                .err()
+                .and_then(|e| self.requests.retry_delay(attempt, e))
+            {
+                Some(delay) => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    async fn list_queues(
+        &self,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        let rows: Vec<QueueRow> = self
+            .list_paged(&["queues", &self.vhost], QUEUE_COLUMNS, name_filter)
+            .await?;
+        Ok(rows.into_iter().map(Queue::from).collect())
+    }
+
+    async fn list_paged<T: DeserializeOwned>(
+        &self,
+        path: &[&str],
+        columns: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let mut listing = Listing::new(&self.endpoint, path, columns, name_filter)?;
+        loop {
+            let page_url = &listing.next_page_url();
+            let response: Page<T> = self
+                .request(|| async move {
+                    self.http
+                        .get(page_url.clone())
+                        .basic_auth(&self.username, Some(&self.password))
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await
+                })
+                .await?;
+            if listing.add(response) {
+                return Ok(listing.items);
+            }
+        }
+    }
+}
+
+async fn unless_stopped(
+    stop: &AtomicBool,
+    action: impl Future<Output = ObjectResult>,
+) -> Option<ObjectResult> {
+    if stop.load(Ordering::SeqCst) {
+        None
+    } else {
+        Some(action.await)
+    }
+}
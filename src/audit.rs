@@ -0,0 +1,204 @@
+use crate::client::RmqClient;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub struct AuditSettings {
+    endpoint: String,
+    path: Option<PathBuf>,
+    syslog: bool,
+}
+
+/// Object an audited action is applied to
+pub struct AuditedObject<'a> {
+    kind: &'static str,
+    name: &'a str,
+    messages: Option<u64>,
+}
+
+impl<'a> AuditedObject<'a> {
+    pub fn queue(name: &'a str, messages: u64) -> AuditedObject<'a> {
+        AuditedObject {
+            kind: "queue",
+            name,
+            messages: Some(messages),
+        }
+    }
+
+    pub fn exchange(name: &'a str) -> AuditedObject<'a> {
+        AuditedObject {
+            kind: "exchange",
+            name,
+            messages: None,
+        }
+    }
+
+    pub fn connection(name: &'a str) -> AuditedObject<'a> {
+        AuditedObject {
+            kind: "connection",
+            name,
+            messages: None,
+        }
+    }
+
+    pub fn policy(name: &'a str, operator: bool) -> AuditedObject<'a> {
+        AuditedObject {
+            kind: if operator {
+                "operator-policy"
+            } else {
+                "policy"
+            },
+            name,
+            messages: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: String,
+    operator: String,
+    endpoint: &'a str,
+    vhost: &'a str,
+    object_type: &'static str,
+    object: &'a str,
+    messages: Option<u64>,
+    action: &'a str,
+    dry_run: bool,
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl AuditSettings {
+    /// Returns `None` if auditing is off
+    pub fn new(
+        endpoint: &str,
+        path: Option<PathBuf>,
+        syslog: bool,
+    ) -> Result<Option<AuditSettings>, Box<dyn std::error::Error>> {
+        if cfg!(not(unix)) && syslog {
+            return Err("Syslog is supported only on Unix".into());
+        }
+
+        Ok((path.is_some() || syslog).then(|| AuditSettings {
+            endpoint: endpoint.to_string(),
+            path,
+            syslog,
+        }))
+    }
+}
+
+/// Runs a destructive action on an object, unless it's a dry run, and writes audit entries about it: one with outcome
+/// `attempted` before the action and one with its outcome after it. If the first entry can't be written, the action
+/// isn't run, so nothing is done without a record. If the second can't, the error says how the action ended
+pub fn audited(
+    rc: &RmqClient,
+    vhost: &str,
+    action: &str,
+    object: AuditedObject,
+    dry_run: bool,
+    f: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    audited_with(rc.audit.as_ref(), vhost, action, object, dry_run, f)
+}
+
+/// Like [`audited`] for callers that don't have the client
+pub(crate) fn audited_with(
+    audit: Option<&AuditSettings>,
+    vhost: &str,
+    action: &str,
+    object: AuditedObject,
+    dry_run: bool,
+    f: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(config) = audit else {
+        return if dry_run { Ok(()) } else { f() };
+    };
+
+    if dry_run {
+        return config.write(vhost, action, &object, true, "skipped", None);
+    }
+
+    config.write(vhost, action, &object, false, "attempted", None)?;
+    let result = f();
+    let written = match &result {
+        Ok(()) => config.write(vhost, action, &object, false, "succeeded", None),
+        Err(e) => config.write(vhost, action, &object, false, "failed", Some(e.to_string())),
+    };
+
+    match (result, written) {
+        (result, Ok(())) => result,
+        (Ok(()), Err(e)) => Err(format!(
+            "{} of {} {} succeeded, but its outcome can't be audited: {}",
+            action, object.kind, object.name, e
+        )
+        .into()),
+        (Err(action_error), Err(e)) => Err(format!(
+            "{} of {} {} failed: {}. Its outcome can't be audited: {}",
+            action, object.kind, object.name, action_error, e
+        )
+        .into()),
+    }
+}
+
+impl AuditSettings {
+    fn write(
+        &self,
+        vhost: &str,
+        action: &str,
+        object: &AuditedObject,
+        dry_run: bool,
+        outcome: &'static str,
+        error: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let entry = Entry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            operator: operator(),
+            endpoint: &self.endpoint,
+            vhost,
+            object_type: object.kind,
+            object: object.name,
+            messages: object.messages,
+            action,
+            dry_run,
+            outcome,
+            error,
+        };
+        let line = serde_json::to_string(&entry)?;
+
+        if let Some(path) = &self.path {
+            // the file is opened for every entry, so it can be rotated while clean_rmq runs
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            file.write_all(format!("{}\n", line).as_bytes())?;
+        }
+        if self.syslog {
+            send_to_syslog(&line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn operator() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(unix)]
+fn send_to_syslog(line: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // facility user (1), severity notice (5)
+    let message = format!("<13>clean_rmq[{}]: {}", std::process::id(), line);
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.send_to(message.as_bytes(), "/dev/log")?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_to_syslog(_line: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Syslog is supported only on Unix".into())
+}
@@ -0,0 +1,828 @@
+use crate::DeleteOptions;
+use crate::audit::{AuditSettings, AuditedObject, audited_with};
+use crate::client::RmqClient;
+use crate::collector::{
+    CollectedObjects, CollectedPolicy, ExchangeName, Queue, collect_objects, collect_queues,
+    revalidate_queue, select_queues_on_nodes, wait_for_quorum,
+};
+use crate::management::ManagementApi;
+use crate::metrics::{Counter, Metrics};
+use crate::parallel::for_each_parallel;
+use crate::request::RequestSettings;
+use crate::safety::Limits;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Cleans a virtual host. Objects are collected into a plan first, so it can be inspected before anything is changed.
+/// Nothing is printed besides exceeded limits in dry run mode, progress can be observed with
+/// [`CleanerBuilder::on_progress`]. The command line purges and deletes with a cleaner too.
+///
+/// **Protected clusters of profiles don't apply here.** A cleaner never asks for confirmation, limit it with
+/// [`CleanerBuilder::max_queues`] and the other limits and inspect the plan before changing anything
+pub struct Cleaner<'a, A: ManagementApi + Sync = RmqClient<'a>> {
+    api: A,
+    vhost: String,
+    dry_run: bool,
+    concurrency: u16,
+    keep_going: bool,
+    revalidate: Option<u64>,
+    wait_for_quorum: Duration,
+    limits: Limits,
+    /// Metrics of the builder, recorded per action
+    metrics: Option<Metrics>,
+    /// Audit log of the command line
+    audit: Option<&'a AuditSettings>,
+    /// Metrics of the command line, counted as objects are changed under the labels of its rule
+    command_line_metrics: Option<&'a Metrics>,
+    progress: Box<dyn Fn(&ObjectResult) + 'a>,
+}
+
+/// Settings of a [`Cleaner`] or an `AsyncCleaner`. `P` is the type of the progress callback
+pub struct CleanerBuilder<'a, P = fn(&ObjectResult)> {
+    pub(crate) endpoint: &'a str,
+    pub(crate) username: &'a str,
+    pub(crate) password: &'a str,
+    pub(crate) vhost: String,
+    pub(crate) dry_run: bool,
+    pub(crate) concurrency: u16,
+    pub(crate) keep_going: bool,
+    pub(crate) request_timeout: Duration,
+    pub(crate) retries: u32,
+    pub(crate) max_requests_per_second: Option<NonZeroU32>,
+    pub(crate) wait_for_quorum: Duration,
+    pub(crate) limits: Limits,
+    pub(crate) metrics_textfile: Option<PathBuf>,
+    pub(crate) metrics_listen: Option<SocketAddr>,
+    pub(crate) progress: P,
+}
+
+/// How purging and deleting behave, besides the broker they talk to
+pub(crate) struct ActionSettings<'s> {
+    pub dry_run: bool,
+    pub concurrency: u16,
+    /// Number of new messages a queue may get since collection, if queues are fetched again right before acting
+    pub revalidate: Option<u64>,
+    pub keep_going: bool,
+    pub wait_for_quorum: Duration,
+    pub audit: Option<&'s AuditSettings>,
+    pub metrics: Option<&'s Metrics>,
+}
+
+/// Order in which both cleaners delete the objects of a plan: queues, then exchanges, then policies one by one.
+/// Nothing is started after a failure unless `keep_going` is set
+pub(crate) struct DeleteOrder<'o> {
+    steps: std::vec::IntoIter<DeleteStep<'o>>,
+    keep_going: bool,
+}
+
+pub(crate) enum DeleteStep<'o> {
+    /// Queues deleted in parallel. Unhealthy quorum queues share the wait, so it doesn't grow with their number
+    Queues {
+        queues: &'o [Queue],
+        wait_for_quorum: bool,
+        quorum_deadline: Option<Instant>,
+    },
+    /// Exchanges deleted in parallel
+    Exchanges(&'o [ExchangeName]),
+    /// A single policy, deleted after the previous one
+    Policy(&'o CollectedPolicy),
+}
+
+/// Queues selected by name
+#[derive(Clone)]
+pub struct QueueSelection {
+    pub(crate) filter: String,
+    pub(crate) exclude: Vec<String>,
+    pub(crate) node: Option<String>,
+    pub(crate) replica_node: Option<String>,
+}
+
+/// Objects selected for deletion, the same as with the options of the delete command
+#[derive(Clone, Default)]
+pub struct DeleteSelection {
+    pub(crate) options: DeleteOptions,
+}
+
+/// Kind of an object that was purged or deleted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ObjectKind {
+    Queue,
+    Exchange,
+    Policy,
+    OperatorPolicy,
+}
+
+/// What happened to a single object
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// The object was purged or deleted, or would have been in dry run mode
+    Done,
+    /// Exclusive queues belong to their connection and are never changed
+    SkippedExclusive,
+    /// Streams can't be purged, their retention has to be reduced instead
+    SkippedStream,
+    /// The queue changed since it was collected, with the reason
+    SkippedChanged(String),
+    /// A quorum queue had no leader or offline members and didn't recover in time, with the reason
+    SkippedUnhealthy(String),
+    /// The request failed, with the error
+    Failed(String),
+}
+
+/// Result of a single object of the plan
+#[derive(Clone, Debug)]
+pub struct ObjectResult {
+    /// Whether the object is a queue, an exchange or a policy
+    pub kind: ObjectKind,
+    /// Name of the queue, exchange or policy
+    pub name: String,
+    /// Number of messages of a queue when it was collected, `None` for other kinds
+    pub messages: Option<u64>,
+    /// What happened to the object
+    pub outcome: Outcome,
+}
+
+/// Results of an action for all objects of the plan, in the order of the plan
+#[derive(Debug)]
+pub struct ActionResult {
+    /// Nothing was changed, the objects report what would have been done
+    pub dry_run: bool,
+    /// Objects in the order of the plan. After a failure without `keep_going` the objects that weren't started are missing
+    pub objects: Vec<ObjectResult>,
+}
+
+impl<'a> Cleaner<'a> {
+    /// Starts building a cleaner of the management API at `endpoint`, e.g. `http://localhost:15672/api`
+    pub fn builder(endpoint: &'a str, username: &'a str, password: &'a str) -> CleanerBuilder<'a> {
+        CleanerBuilder {
+            endpoint,
+            username,
+            password,
+            vhost: "/".to_string(),
+            dry_run: false,
+            concurrency: 1,
+            keep_going: false,
+            request_timeout: Duration::from_secs(30),
+            retries: 3,
+            max_requests_per_second: None,
+            wait_for_quorum: Duration::ZERO,
+            limits: Limits::default(),
+            metrics_textfile: None,
+            metrics_listen: None,
+            progress: |_| {},
+        }
+    }
+}
+
+impl<'r, 'c> Cleaner<'r, &'r RmqClient<'c>> {
+    /// Cleaner of the command line, with the audit log, the metrics and the quorum wait of the client. The command line
+    /// checks the limits itself, before it changes anything else like closing connections
+    pub(crate) fn for_command_line(
+        rc: &'r RmqClient<'c>,
+        vhost: &str,
+        dry_run: bool,
+        concurrency: u16,
+        revalidate: Option<u64>,
+        progress: impl Fn(&ObjectResult) + 'r,
+    ) -> Self {
+        Cleaner {
+            api: rc,
+            vhost: vhost.to_string(),
+            dry_run,
+            concurrency,
+            keep_going: false,
+            revalidate,
+            wait_for_quorum: rc.wait_for_quorum,
+            limits: Limits::default(),
+            metrics: None,
+            audit: rc.audit.as_ref(),
+            command_line_metrics: Some(&rc.metrics),
+            progress: Box::new(progress),
+        }
+    }
+}
+
+impl<A: ManagementApi + Sync> Cleaner<'_, A> {
+    /// Virtual host the cleaner works on
+    pub fn vhost(&self) -> &str {
+        &self.vhost
+    }
+
+    /// Collects queues with messages to purge. Stream queues are collected too, but skipped when purging
+    pub fn plan_purge(
+        &self,
+        selection: &QueueSelection,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        let queues = collect_queues(
+            &self.api,
+            &self.vhost,
+            &selection.filter,
+            &selection.exclude,
+        )?;
+        select_queues_on_nodes(
+            queues,
+            selection.node.as_deref(),
+            selection.replica_node.as_deref(),
+        )
+    }
+
+    /// Purges the queues after checking them against the limits. Failed queues are in the result, see
+    /// [`ActionResult::failed`]
+    pub fn purge(&self, queues: &[Queue]) -> Result<ActionResult, Box<dyn std::error::Error>> {
+        self.limits.check(self.dry_run, "purge", queues, 0)?;
+        let started = Instant::now();
+        let result = purge_queues(
+            &self.api,
+            &self.settings(),
+            &self.vhost,
+            queues,
+            &self.progress,
+        );
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&self.vhost, "purge", &result, started.elapsed())?;
+        }
+        Ok(result)
+    }
+
+    /// Collects queues, exchanges and policies to delete
+    pub fn plan_delete(
+        &self,
+        selection: &DeleteSelection,
+    ) -> Result<CollectedObjects, Box<dyn std::error::Error>> {
+        collect_objects(&self.api, &self.vhost, &selection.options)
+    }
+
+    /// Deletes the objects after checking them against the limits. Failed objects are in the result, see
+    /// [`ActionResult::failed`]
+    pub fn delete(
+        &self,
+        objects: &CollectedObjects,
+    ) -> Result<ActionResult, Box<dyn std::error::Error>> {
+        self.limits.check(
+            self.dry_run,
+            "delete",
+            &objects.queues,
+            objects.exchanges.len(),
+        )?;
+        let started = Instant::now();
+        let result = delete_objects(
+            &self.api,
+            &self.settings(),
+            &self.vhost,
+            objects,
+            &self.progress,
+        );
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&self.vhost, "delete", &result, started.elapsed())?;
+        }
+        Ok(result)
+    }
+
+    fn settings(&self) -> ActionSettings<'_> {
+        ActionSettings {
+            dry_run: self.dry_run,
+            concurrency: self.concurrency,
+            revalidate: self.revalidate,
+            keep_going: self.keep_going,
+            wait_for_quorum: self.wait_for_quorum,
+            audit: self.audit,
+            // metrics of the builder are recorded from the result instead, like the async cleaner does
+            metrics: self.command_line_metrics,
+        }
+    }
+}
+
+impl<'a, P: Fn(&ObjectResult) + 'a> CleanerBuilder<'a, P> {
+    /// Virtual host to clean, `/` by default
+    pub fn vhost(mut self, vhost: &str) -> Self {
+        self.vhost = vhost.to_string();
+        self
+    }
+
+    /// Only report what would be done
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Number of queues or exchanges changed in parallel
+    pub fn concurrency(mut self, concurrency: u16) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Timeout of a single request to the management API, 30 seconds by default
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Go on with the other objects after one failed. By default no new objects are started after a failure,
+    /// the ones already in progress are finished
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Refuse to purge or delete more queues than this, like --max-queues
+    pub fn max_queues(mut self, max_queues: u64) -> Self {
+        self.limits.max_queues = Some(max_queues);
+        self
+    }
+
+    /// Refuse to delete more exchanges than this, like --max-exchanges
+    pub fn max_exchanges(mut self, max_exchanges: u64) -> Self {
+        self.limits.max_exchanges = Some(max_exchanges);
+        self
+    }
+
+    /// Refuse to purge or delete queues with more messages in total than this, like --max-messages
+    pub fn max_messages(mut self, max_messages: u64) -> Self {
+        self.limits.max_messages = Some(max_messages);
+        self
+    }
+
+    /// Retries of requests failed with 429, 5xx or a timeout
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Rate limit of requests to the management API, shared by all parallel requests. Unlimited by default
+    pub fn max_requests_per_second(mut self, max_requests_per_second: NonZeroU32) -> Self {
+        self.max_requests_per_second = Some(max_requests_per_second);
+        self
+    }
+
+    /// How long to wait for unhealthy quorum queues to recover before skipping them when deleting. The time is
+    /// shared by all queues of a delete
+    pub fn wait_for_quorum(mut self, wait: Duration) -> Self {
+        self.wait_for_quorum = wait;
+        self
+    }
+
+    /// Counts purged messages and deleted objects like the command line and writes them to this node-exporter
+    /// textfile after every action
+    pub fn metrics_textfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.metrics_textfile = Some(path.into());
+        self
+    }
+
+    /// Serves the metrics for Prometheus on this address
+    pub fn metrics_listen(mut self, addr: SocketAddr) -> Self {
+        self.metrics_listen = Some(addr);
+        self
+    }
+
+    /// Called with the result of every object as soon as it's known, in the order of the plan.
+    /// [`build_async`](CleanerBuilder::build_async) requires it to be `Send` and `Sync`
+    pub fn on_progress<Q: Fn(&ObjectResult) + 'a>(self, progress: Q) -> CleanerBuilder<'a, Q> {
+        CleanerBuilder {
+            endpoint: self.endpoint,
+            username: self.username,
+            password: self.password,
+            vhost: self.vhost,
+            dry_run: self.dry_run,
+            concurrency: self.concurrency,
+            keep_going: self.keep_going,
+            request_timeout: self.request_timeout,
+            retries: self.retries,
+            max_requests_per_second: self.max_requests_per_second,
+            wait_for_quorum: self.wait_for_quorum,
+            limits: self.limits,
+            metrics_textfile: self.metrics_textfile,
+            metrics_listen: self.metrics_listen,
+            progress,
+        }
+    }
+
+    /// Metrics with the textfile and the listener of the builder, if it has either
+    pub(crate) fn metrics(&self) -> Result<Option<Metrics>, Box<dyn std::error::Error>> {
+        if self.metrics_textfile.is_none() && self.metrics_listen.is_none() {
+            return Ok(None);
+        }
+        let metrics = Metrics::new(self.metrics_textfile.clone());
+        metrics.listen(self.metrics_listen)?;
+        Ok(Some(metrics))
+    }
+
+    pub fn build(self) -> Result<Cleaner<'a>, Box<dyn std::error::Error>> {
+        let mut rc = RmqClient::new(
+            self.endpoint,
+            self.username,
+            self.password,
+            self.request_timeout,
+        )?;
+        rc.requests = RequestSettings::new(self.max_requests_per_second, self.retries);
+        self.build_with(rc)
+    }
+
+    /// Builds a cleaner that talks to the broker through `api` instead, e.g. a `FakeBroker` of the `testing` feature.
+    /// The endpoint, credentials, request timeout, retries and rate limit aren't used then
+    pub fn build_with<A: ManagementApi + Sync>(
+        self,
+        api: A,
+    ) -> Result<Cleaner<'a, A>, Box<dyn std::error::Error>> {
+        let metrics = self.metrics()?;
+        Ok(Cleaner {
+            api,
+            vhost: self.vhost,
+            dry_run: self.dry_run,
+            concurrency: self.concurrency,
+            keep_going: self.keep_going,
+            revalidate: None,
+            wait_for_quorum: self.wait_for_quorum,
+            limits: self.limits,
+            metrics,
+            audit: None,
+            command_line_metrics: None,
+            progress: Box::new(self.progress),
+        })
+    }
+}
+
+impl Default for QueueSelection {
+    fn default() -> Self {
+        QueueSelection::matching(".+")
+    }
+}
+
+impl QueueSelection {
+    /// Selects queues with names matching the regex
+    pub fn matching(filter: &str) -> Self {
+        QueueSelection {
+            filter: filter.to_string(),
+            exclude: vec![],
+            node: None,
+            replica_node: None,
+        }
+    }
+
+    /// Skips queues with names matching the regex. Can be called multiple times
+    pub fn exclude(mut self, filter: &str) -> Self {
+        self.exclude.push(filter.to_string());
+        self
+    }
+
+    /// Selects only queues hosted on a node matching the regex, the leader for quorum queues and streams
+    pub fn on_node(mut self, filter: &str) -> Self {
+        self.node = Some(filter.to_string());
+        self
+    }
+
+    /// Selects only queues with a replica on a node matching the regex
+    pub fn with_replica_on(mut self, filter: &str) -> Self {
+        self.replica_node = Some(filter.to_string());
+        self
+    }
+}
+
+impl DeleteSelection {
+    /// Selects nothing, add the kinds of objects to delete
+    pub fn new() -> Self {
+        DeleteSelection::default()
+    }
+
+    /// Selects queues, like `delete --queues` with the filters of the selection
+    pub fn queues(mut self, selection: QueueSelection) -> Self {
+        self.options.queues = true;
+        self.options.queue_filter = selection.filter;
+        self.options.exclude_queue_filter = selection.exclude;
+        self.options.node = selection.node;
+        self.options.replica_node = selection.replica_node;
+        self
+    }
+
+    /// Skips selected queues that have consumers
+    pub fn without_consumers(mut self) -> Self {
+        self.options.queues_without_consumers = true;
+        self
+    }
+
+    /// Selects only quorum queues without a leader or with offline members. They are deleted without waiting for quorum
+    pub fn quorum_unhealthy(mut self) -> Self {
+        self.options.quorum_unhealthy = true;
+        self
+    }
+
+    /// Selects all exchanges except the default and `amq.*` ones, like `delete --exchanges`
+    pub fn exchanges(mut self) -> Self {
+        self.options.exchanges = true;
+        self
+    }
+
+    /// Selects only exchanges whose messages don't end up in a surviving queue
+    pub fn exchanges_without_destination(mut self) -> Self {
+        self.options.exchanges = true;
+        self.options.exchanges_without_destination = true;
+        self
+    }
+
+    /// Selects policies that don't apply to any surviving queue or exchange
+    pub fn policies(mut self) -> Self {
+        self.options.policies = true;
+        self
+    }
+
+    pub fn operator_policies(mut self) -> Self {
+        self.options.policies = true;
+        self.options.operator_policies = true;
+        self
+    }
+}
+
+impl ActionResult {
+    /// Number of objects of the kind that were purged or deleted
+    pub fn done(&self, kind: ObjectKind) -> usize {
+        self.count(|object| object.kind == kind && object.outcome == Outcome::Done)
+    }
+
+    /// Number of messages in purged or deleted queues
+    pub fn messages(&self) -> u64 {
+        self.objects
+            .iter()
+            .filter(|object| object.outcome == Outcome::Done)
+            .filter_map(|object| object.messages)
+            .sum()
+    }
+
+    /// Number of exclusive queues, which are never purged or deleted
+    pub fn skipped_exclusive(&self) -> usize {
+        self.count(|object| object.outcome == Outcome::SkippedExclusive)
+    }
+
+    /// Number of stream queues skipped when purging
+    pub fn skipped_streams(&self) -> usize {
+        self.count(|object| object.outcome == Outcome::SkippedStream)
+    }
+
+    /// Number of queues skipped because they changed since they were collected
+    pub fn skipped_changed(&self) -> usize {
+        self.count(|object| matches!(object.outcome, Outcome::SkippedChanged(_)))
+    }
+
+    /// Number of quorum queues skipped because they didn't recover in time
+    pub fn skipped_unhealthy(&self) -> usize {
+        self.count(|object| matches!(object.outcome, Outcome::SkippedUnhealthy(_)))
+    }
+
+    fn count(&self, f: impl Fn(&ObjectResult) -> bool) -> usize {
+        self.objects.iter().filter(|object| f(object)).count()
+    }
+
+    /// Number of objects whose request failed
+    pub fn failed(&self) -> usize {
+        self.count(|object| matches!(object.outcome, Outcome::Failed(_)))
+    }
+
+    /// Error of the first failed object
+    pub fn first_error(&self) -> Option<&str> {
+        self.objects
+            .iter()
+            .find_map(|object| match &object.outcome {
+                Outcome::Failed(e) => Some(e.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Whether to stop before the next kind of objects, as one failed without `keep_going`
+    pub(crate) fn stopped(&self, keep_going: bool) -> bool {
+        !keep_going && self.failed() > 0
+    }
+
+    /// Reports the result of an object and keeps it, failed or not
+    pub(crate) fn record(&mut self, object: ObjectResult, progress: &dyn Fn(&ObjectResult)) {
+        progress(&object);
+        self.objects.push(object);
+    }
+}
+
+impl<'o> DeleteOrder<'o> {
+    /// Starts deleting the objects, the wait for quorum starts now
+    pub(crate) fn new(
+        objects: &'o CollectedObjects,
+        wait_for_quorum: Duration,
+        keep_going: bool,
+    ) -> Self {
+        let mut steps = vec![
+            DeleteStep::Queues {
+                queues: &objects.queues,
+                wait_for_quorum: !objects.unhealthy_quorum_queues,
+                quorum_deadline: quorum_deadline(wait_for_quorum),
+            },
+            DeleteStep::Exchanges(&objects.exchanges),
+        ];
+        steps.extend(objects.policies.iter().map(DeleteStep::Policy));
+        DeleteOrder {
+            steps: steps.into_iter(),
+            keep_going,
+        }
+    }
+
+    /// Next step, `None` when done or stopped by a failure in the result so far
+    pub(crate) fn next(&mut self, result: &ActionResult) -> Option<DeleteStep<'o>> {
+        if result.stopped(self.keep_going) {
+            return None;
+        }
+        self.steps.next()
+    }
+}
+
+impl ActionSettings<'_> {
+    fn count(&self, counter: Counter, value: u64) {
+        if !self.dry_run
+            && let Some(metrics) = self.metrics
+        {
+            metrics.add(counter, value);
+        }
+    }
+}
+
+pub(crate) fn purge_queues(
+    api: &(impl ManagementApi + Sync),
+    settings: &ActionSettings,
+    vhost: &str,
+    queues: &[Queue],
+    progress: &dyn Fn(&ObjectResult),
+) -> ActionResult {
+    let mut result = ActionResult {
+        dry_run: settings.dry_run,
+        objects: vec![],
+    };
+    for_each_parallel(
+        queues,
+        settings.concurrency,
+        settings.keep_going,
+        |queue| {
+            if queue.is_stream() {
+                return Ok(Outcome::SkippedStream);
+            }
+            act_on_queue(api, settings, vhost, "purge", queue, || {
+                api.purge(vhost, &queue.name)
+            })
+        },
+        |queue, outcome| {
+            if outcome == Ok(Outcome::Done) {
+                settings.count(Counter::MessagesPurged, queue.messages);
+            }
+            result.record(queue_result(queue, outcome), progress);
+        },
+    );
+
+    result
+}
+
+pub(crate) fn delete_objects(
+    api: &(impl ManagementApi + Sync),
+    settings: &ActionSettings,
+    vhost: &str,
+    objects: &CollectedObjects,
+    progress: &dyn Fn(&ObjectResult),
+) -> ActionResult {
+    let mut result = ActionResult {
+        dry_run: settings.dry_run,
+        objects: vec![],
+    };
+
+    let mut order = DeleteOrder::new(objects, settings.wait_for_quorum, settings.keep_going);
+    while let Some(step) = order.next(&result) {
+        match step {
+            DeleteStep::Queues {
+                queues,
+                wait_for_quorum: wait,
+                quorum_deadline,
+            } => for_each_parallel(
+                queues,
+                settings.concurrency,
+                settings.keep_going,
+                |queue| {
+                    if !queue.exclusive
+                        && wait
+                        && let Some(reason) =
+                            wait_for_quorum(api, vhost, queue, settings.dry_run, quorum_deadline)
+                                .map_err(|e| e.to_string())?
+                    {
+                        return Ok(Outcome::SkippedUnhealthy(reason));
+                    }
+                    act_on_queue(api, settings, vhost, "delete", queue, || {
+                        api.remove_queue(vhost, &queue.name)
+                    })
+                },
+                |queue, outcome| {
+                    if outcome == Ok(Outcome::Done) {
+                        settings.count(Counter::QueuesDeleted, 1);
+                    }
+                    result.record(queue_result(queue, outcome), progress);
+                },
+            ),
+            DeleteStep::Exchanges(exchanges) => for_each_parallel(
+                exchanges,
+                settings.concurrency,
+                settings.keep_going,
+                |exchange| {
+                    audited_with(
+                        settings.audit,
+                        vhost,
+                        "delete",
+                        AuditedObject::exchange(exchange),
+                        settings.dry_run,
+                        || api.remove_exchange(vhost, exchange),
+                    )
+                    .map(|()| Outcome::Done)
+                    .map_err(|e| e.to_string())
+                },
+                |exchange, outcome| {
+                    if outcome.is_ok() {
+                        settings.count(Counter::ExchangesDeleted, 1);
+                    }
+                    result.record(exchange_result(exchange, outcome), progress);
+                },
+            ),
+            DeleteStep::Policy(policy) => {
+                let outcome = audited_with(
+                    settings.audit,
+                    vhost,
+                    "delete",
+                    AuditedObject::policy(&policy.name, policy.operator),
+                    settings.dry_run,
+                    || api.remove_policy(vhost, &policy.name, policy.operator),
+                );
+                let outcome = outcome.map_err(|e| e.to_string()).map(|()| Outcome::Done);
+                result.record(policy_result(policy, outcome), progress);
+            }
+        }
+    }
+
+    result
+}
+
+/// Runs an audited request on a queue unless it's exclusive or, with `revalidate`, changed since it was collected
+fn act_on_queue(
+    api: &impl ManagementApi,
+    settings: &ActionSettings,
+    vhost: &str,
+    action: &str,
+    queue: &Queue,
+    f: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<Outcome, String> {
+    if queue.exclusive {
+        return Ok(Outcome::SkippedExclusive);
+    }
+    if let Some(max_message_increase) = settings.revalidate
+        && let Some(reason) =
+            revalidate_queue(api, vhost, queue, max_message_increase).map_err(|e| e.to_string())?
+    {
+        return Ok(Outcome::SkippedChanged(reason));
+    }
+    audited_with(
+        settings.audit,
+        vhost,
+        action,
+        AuditedObject::queue(&queue.name, queue.messages),
+        settings.dry_run,
+        f,
+    )
+    .map(|()| Outcome::Done)
+    .map_err(|e| e.to_string())
+}
+
+/// End of the wait for unhealthy quorum queues of a delete starting now, `None` if it doesn't wait
+fn quorum_deadline(wait: Duration) -> Option<Instant> {
+    (!wait.is_zero()).then(|| Instant::now() + wait)
+}
+
+pub(crate) fn queue_result(queue: &Queue, outcome: Result<Outcome, String>) -> ObjectResult {
+    ObjectResult {
+        kind: ObjectKind::Queue,
+        name: queue.name.clone(),
+        messages: Some(queue.messages),
+        outcome: outcome.unwrap_or_else(Outcome::Failed),
+    }
+}
+
+pub(crate) fn exchange_result(exchange: &str, outcome: Result<Outcome, String>) -> ObjectResult {
+    ObjectResult {
+        kind: ObjectKind::Exchange,
+        name: exchange.to_string(),
+        messages: None,
+        outcome: outcome.unwrap_or_else(Outcome::Failed),
+    }
+}
+
+pub(crate) fn policy_result(
+    policy: &CollectedPolicy,
+    outcome: Result<Outcome, String>,
+) -> ObjectResult {
+    ObjectResult {
+        kind: if policy.operator {
+            ObjectKind::OperatorPolicy
+        } else {
+            ObjectKind::Policy
+        },
+        name: policy.name.clone(),
+        messages: None,
+        outcome: outcome.unwrap_or_else(Outcome::Failed),
+    }
+}
@@ -0,0 +1,343 @@
+use crate::audit::AuditSettings;
+use crate::collector::{ExchangeName, Queue};
+use crate::metrics::Metrics;
+use crate::request::RequestSettings;
+use crate::safety::{Limits, Protection};
+use rabbitmq_http_client::blocking_api::{Client, ClientBuilder};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+use std::ops::Deref;
+use std::time::Duration;
+use url::Url;
+
+const PAGE_SIZE: u32 = 500;
+
+/// Client of the management API together with the settings of the run that apply to all actions
+pub struct RmqClient<'a> {
+    client: Client<&'a str, &'a str, &'a str>,
+    http: reqwest::blocking::Client,
+    endpoint: &'a str,
+    username: &'a str,
+    password: &'a str,
+    pub(crate) requests: RequestSettings,
+    pub(crate) audit: Option<AuditSettings>,
+    pub(crate) metrics: Metrics,
+    pub(crate) limits: Limits,
+    pub(crate) protection: Option<Protection>,
+    pub(crate) wait_for_quorum: Duration,
+}
+
+impl<'a> Deref for RmqClient<'a> {
+    type Target = Client<&'a str, &'a str, &'a str>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Page<T> {
+    pub items: Vec<T>,
+    pub page_count: u32,
+}
+
+/// Items of a paged listing collected page by page, shared by the blocking and the async client
+pub(crate) struct Listing<'l, T> {
+    url: Url,
+    columns: &'l str,
+    name_filter: Option<&'l str>,
+    page: u32,
+    pub items: Vec<T>,
+}
+
+impl<'l, T> Listing<'l, T> {
+    pub fn new(
+        endpoint: &str,
+        path: &[&str],
+        columns: &'l str,
+        name_filter: Option<&'l str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Listing {
+            url: listing_url(endpoint, path)?,
+            columns,
+            name_filter,
+            page: 1,
+            items: vec![],
+        })
+    }
+
+    pub fn next_page_url(&self) -> Url {
+        page_url(&self.url, self.page, self.columns, self.name_filter)
+    }
+
+    /// Adds the items of the page that was requested last, returns whether it was the last page
+    pub fn add(&mut self, response: Page<T>) -> bool {
+        self.items.extend(response.items);
+        self.page += 1;
+        self.page > response.page_count
+    }
+}
+
+/// Only the columns `Queue` needs. Fields may be missing, e.g. `messages` of a queue that was just declared
+#[derive(Deserialize)]
+pub(crate) struct QueueRow {
+    name: String,
+    #[serde(default)]
+    messages: u64,
+    #[serde(default)]
+    exclusive: bool,
+    #[serde(default, rename = "type")]
+    queue_type: String,
+    #[serde(default)]
+    consumers: u16,
+    #[serde(default)]
+    node: String,
+    #[serde(default)]
+    leader: Option<String>,
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    online: Vec<String>,
+}
+
+pub(crate) const QUEUE_COLUMNS: &str =
+    "name,messages,exclusive,type,consumers,node,leader,members,online";
+
+/// Node of the cluster. Nodes that are down report little more than their name
+#[derive(Deserialize)]
+pub struct Node {
+    pub name: String,
+    #[serde(default)]
+    pub running: bool,
+    #[serde(default)]
+    pub being_drained: bool,
+    /// Milliseconds since the node started
+    #[serde(default)]
+    pub uptime: u64,
+}
+
+const NODE_COLUMNS: &str = "name,running,being_drained,uptime";
+
+#[derive(Deserialize)]
+struct Routed {
+    routed: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct NameRow {
+    pub name: String,
+}
+
+impl From<QueueRow> for Queue {
+    fn from(row: QueueRow) -> Self {
+        Queue {
+            name: row.name,
+            messages: row.messages,
+            exclusive: row.exclusive,
+            queue_type: row.queue_type,
+            consumer_count: row.consumers,
+            node: row.node,
+            leader: row.leader,
+            members: row.members,
+            online: row.online,
+        }
+    }
+}
+
+/// URL of a listing of the management API, `path` being its unencoded segments
+pub(crate) fn listing_url(
+    endpoint: &str,
+    path: &[&str],
+) -> Result<Url, Box<dyn std::error::Error>> {
+    let mut url = Url::parse(endpoint)?;
+    url.path_segments_mut()
+        .map_err(|_| "Endpoint can't have a path")?
+        .pop_if_empty()
+        .extend(path);
+    Ok(url)
+}
+
+/// URL of a page of a listing with only the given columns and, optionally, names matching the regex.
+/// The server matches names with Erlang's PCRE regexes, so the filter is sent only if both dialects read it the same way.
+/// Otherwise every name is listed, as the callers match the names themselves anyway
+pub(crate) fn page_url(url: &Url, page: u32, columns: &str, name_filter: Option<&str>) -> Url {
+    let mut page_url = url.clone();
+    page_url
+        .query_pairs_mut()
+        .append_pair("page", &page.to_string())
+        .append_pair("page_size", &PAGE_SIZE.to_string())
+        .append_pair("columns", columns);
+    if let Some(name_filter) = name_filter.filter(|f| is_portable_regex(f)) {
+        page_url
+            .query_pairs_mut()
+            .append_pair("name", name_filter)
+            .append_pair("use_regex", "true");
+    }
+    page_url
+}
+
+/// Whether the regex uses only literal ASCII characters, anchors, alternatives, groups, quantifiers and `.*` or `.+`,
+/// which Rust and PCRE match the same way. Classes, escapes and `.` alone differ in Unicode and byte handling
+fn is_portable_regex(filter: &str) -> bool {
+    let mut chars = filter.chars().peekable();
+    let mut portable = !filter.is_empty();
+    while let Some(c) = chars.next() {
+        portable &= match c {
+            '.' => matches!(chars.peek(), Some('*' | '+')),
+            '(' => chars.peek() != Some(&'?'),
+            '^' | '$' | '|' | ')' | '*' | '+' | '?' => true,
+            '_' | '-' | ':' | '/' | '@' | '~' | ',' | '=' => true,
+            c => c.is_ascii_alphanumeric(),
+        };
+    }
+    portable
+}
+
+impl<'a> RmqClient<'a> {
+    pub fn new(
+        endpoint: &'a str,
+        username: &'a str,
+        password: &'a str,
+        request_timeout: Duration,
+    ) -> Result<RmqClient<'a>, Box<dyn std::error::Error>> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(request_timeout)
+            .build()?;
+
+        Ok(RmqClient {
+            client: ClientBuilder::new()
+                .with_endpoint(endpoint)
+                .with_basic_auth_credentials(username, password)
+                .with_client(http.clone())
+                .build(),
+            http,
+            endpoint,
+            username,
+            password,
+            requests: RequestSettings::default(),
+            audit: None,
+            metrics: Metrics::default(),
+            limits: Limits::default(),
+            protection: None,
+            wait_for_quorum: Duration::ZERO,
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        self.endpoint
+    }
+
+    /// Lists queues page by page. The name filter is applied by the server to reduce the amount of transferred data,
+    /// its regex dialect differs slightly from ours, so callers still apply the filter themselves
+    pub fn list_queues_paged(
+        &self,
+        vhost: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        let rows: Vec<QueueRow> =
+            self.list_paged(&["queues", vhost], QUEUE_COLUMNS, name_filter)?;
+        Ok(rows.into_iter().map(Queue::from).collect())
+    }
+
+    pub fn list_exchange_names_paged(
+        &self,
+        vhost: &str,
+    ) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>> {
+        let rows: Vec<NameRow> = self.list_paged(&["exchanges", vhost], "name", None)?;
+        Ok(rows.into_iter().map(|row| row.name).collect())
+    }
+
+    /// Lists nodes of the cluster, including the ones that are down
+    pub fn list_cluster_nodes(&self) -> Result<Vec<Node>, Box<dyn std::error::Error>> {
+        let mut url = listing_url(self.endpoint, &["nodes"])?;
+        url.query_pairs_mut().append_pair("columns", NODE_COLUMNS);
+        Ok(self.request(|| {
+            self.http
+                .get(url.clone())
+                .basic_auth(self.username, Some(self.password))
+                .send()?
+                .error_for_status()?
+                .json()
+        })?)
+    }
+
+    /// Publishes a message taken from a queue back to it via the default exchange. Unlike `publish_message` it keeps
+    /// the payload encoding, so binary payloads survive too. Returns whether the broker routed the message
+    pub(crate) fn republish(
+        &self,
+        vhost: &str,
+        queue: &str,
+        payload: &str,
+        payload_encoding: &str,
+        properties: &Map<String, Value>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let url = listing_url(self.endpoint, &["exchanges", vhost, "", "publish"])?;
+        let body = json!({
+            "routing_key": queue,
+            "payload": payload,
+            "payload_encoding": payload_encoding,
+            "properties": properties,
+        });
+        let routed: Routed = self
+            .http
+            .post(url)
+            .basic_auth(self.username, Some(self.password))
+            .json(&body)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(routed.routed)
+    }
+
+    pub(crate) fn list_paged<T: DeserializeOwned>(
+        &self,
+        path: &[&str],
+        columns: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let mut listing = Listing::new(self.endpoint, path, columns, name_filter)?;
+        loop {
+            let page_url = listing.next_page_url();
+            let response: Page<T> = self.request(|| {
+                self.http
+                    .get(page_url.clone())
+                    .basic_auth(self.username, Some(self.password))
+                    .send()?
+                    .error_for_status()?
+                    .json()
+            })?;
+            if listing.add(response) {
+                return Ok(listing.items);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_portable_regexes_are_sent_to_the_server() {
+        for filter in ["^test_", "_keep$", "^(one|idle)$", "^temp_.*_2$", "a+b?"] {
+            assert!(is_portable_regex(filter), "{}", filter);
+        }
+        for filter in ["", r"\d+", "[a-z]", "^.$", "(?i)test", "a{2}", "é"] {
+            assert!(!is_portable_regex(filter), "{}", filter);
+        }
+
+        let url = Url::parse("http://localhost:15672/api/queues/test").unwrap();
+        let sent = page_url(&url, 1, "name", Some("^test_"));
+        assert!(
+            sent.query_pairs()
+                .any(|(k, v)| k == "name" && v == "^test_")
+        );
+        let not_sent = page_url(&url, 1, "name", Some(r"^\w+$"));
+        assert!(
+            !not_sent
+                .query_pairs()
+                .any(|(k, _)| k == "name" || k == "use_regex")
+        );
+    }
+}
@@ -1,81 +1,448 @@
 use crate::DeleteOptions;
-use rabbitmq_http_client::blocking_api::Client;
-use rabbitmq_http_client::commons::BindingDestinationType;
-use rabbitmq_http_client::responses::QueueInfo;
+use crate::management::{Binding, ManagementApi};
+use rabbitmq_http_client::commons::{BindingDestinationType, PolicyTarget};
+use rabbitmq_http_client::responses::{Policy, QueueInfo};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+const QUORUM_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 pub type QueueName = String;
 pub type ExchangeName = String;
-pub type RmqClient<'a> = Client<&'a str, &'a str, &'a str>;
+pub type PolicyName = String;
 
-#[derive(Clone)]
+/// State of a queue when it was collected
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Queue {
     pub name: QueueName,
     pub messages: u64,
+    /// Exclusive queues belong to their connection and are never purged or deleted
     pub exclusive: bool,
-    consumer_count: u16,
+    /// classic, quorum or stream
+    pub queue_type: String,
+    pub consumer_count: u16,
+    /// Node hosting the queue, the leader for quorum queues and streams
+    #[serde(default)]
+    pub node: String,
+    /// Node of the leader of a quorum queue, missing during a leader election
+    #[serde(default)]
+    pub leader: Option<String>,
+    /// Nodes with a member of a quorum queue
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Nodes with a member of a quorum queue that is running
+    #[serde(default)]
+    pub online: Vec<String>,
 }
 
-pub struct CollectedObjects {
-    pub queues: Vec<Queue>,
-    pub exchanges: Vec<ExchangeName>,
+impl Queue {
+    /// Streams keep their messages until retention discards them, so they can't be purged
+    pub fn is_stream(&self) -> bool {
+        self.queue_type == "stream"
+    }
+
+    /// Nodes with a replica of the queue. Classic queues have their only replica on their node
+    pub fn replica_nodes(&self) -> &[String] {
+        if self.members.is_empty() {
+            std::slice::from_ref(&self.node)
+        } else {
+            &self.members
+        }
+    }
+
+    /// Why a quorum queue can't be deleted cleanly: it has no leader or members on nodes that are down
+    pub fn quorum_problem(&self) -> Option<String> {
+        if self.queue_type != "quorum" {
+            return None;
+        }
+        if self.leader.as_deref().is_none_or(str::is_empty) {
+            return Some("it has no leader, an election may be in progress".to_string());
+        }
+
+        let offline: Vec<_> = self
+            .members
+            .iter()
+            .filter(|member| !self.online.contains(member))
+            .map(String::as_str)
+            .collect();
+        if !offline.is_empty() {
+            return Some(format!(
+                "{} of {} members are offline: {}",
+                offline.len(),
+                self.members.len(),
+                offline.join(", ")
+            ));
+        }
+
+        None
+    }
 }
 
-impl Queue {
-    pub fn from(info: QueueInfo) -> Queue {
+impl From<QueueInfo> for Queue {
+    fn from(info: QueueInfo) -> Self {
         Queue {
             name: info.name,
             messages: info.message_count,
             exclusive: info.exclusive,
+            queue_type: info.queue_type,
             consumer_count: info.consumer_count,
+            node: info.node,
+            leader: info.leader,
+            members: info
+                .members
+                .map(|m| m.into_iter().collect())
+                .unwrap_or_default(),
+            online: info
+                .online
+                .map(|m| m.into_iter().collect())
+                .unwrap_or_default(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectedPolicy {
+    pub name: PolicyName,
+    pub operator: bool,
+}
+
+/// Objects of a virtual host the objects to delete are selected from. Parts not needed by the options stay empty
+#[derive(Default)]
+pub(crate) struct Topology {
+    pub queues: Vec<Queue>,
+    pub exchanges: Vec<ExchangeName>,
+    pub bindings: Vec<Binding>,
+    pub policies: Vec<Policy>,
+    pub operator_policies: Vec<Policy>,
+}
+
+/// Listing of a part of the topology, made by the blocking and the async cleaner alike
+pub(crate) enum TopologyRequest<'o> {
+    /// Queues, with the name filter for the broker
+    Queues(Option<&'o str>),
+    Exchanges,
+    Bindings,
+    Policies,
+    OperatorPolicies,
+}
+
+/// Parts of the topology the options of the delete command need besides queues
+struct TopologyNeeds {
+    /// Finding exchanges without destination and unused policies needs the surviving queues too, otherwise only
+    /// queues matching the queue filter are listed
+    all_queues: bool,
+    exchanges: bool,
+    bindings: bool,
+    policies: bool,
+    operator_policies: bool,
+}
+
+/// Objects to delete
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectedObjects {
+    pub queues: Vec<Queue>,
+    /// Selected queues with consumers whose connections have to be closed first
+    pub queues_with_consumers: Vec<QueueName>,
+    pub exchanges: Vec<ExchangeName>,
+    pub policies: Vec<CollectedPolicy>,
+    /// Policies overridden by other policies, reported but not deleted
+    pub policy_warnings: Vec<String>,
+    /// Quorum queues were selected for having no leader or offline members, so they are deleted without waiting for quorum
+    #[serde(default)]
+    pub unhealthy_quorum_queues: bool,
+}
+
 pub fn collect_queues(
-    rc: &RmqClient,
+    api: &impl ManagementApi,
+    vhost: &str,
+    filter: &str,
+    exclude_queue_filters: &[String],
+) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+    let queues = api.queues(vhost, Some(filter))?;
+    select_queues_with_messages(queues, filter, exclude_queue_filters)
+}
+
+/// Collects queues matching the filters regardless of the number of messages in them
+pub fn collect_queues_by_name(
+    api: &impl ManagementApi,
     vhost: &str,
     filter: &str,
-    exclude_queue_filters: &Vec<String>,
+    exclude_queue_filters: &[String],
+) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+    let queues = api.queues(vhost, Some(filter))?;
+    select_queues_by_name(queues, filter, exclude_queue_filters)
+}
+
+pub(crate) fn select_queues_with_messages(
+    queues: Vec<Queue>,
+    filter: &str,
+    exclude_queue_filters: &[String],
+) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+    let queues = select_queues_by_name(queues, filter, exclude_queue_filters)?
+        .into_iter()
+        .filter(|queue| queue.messages > 0)
+        .collect();
+
+    Ok(queues)
+}
+
+/// Selects queues by the nodes hosting them, see [`NodeFilter`]
+pub(crate) fn select_queues_on_nodes(
+    queues: Vec<Queue>,
+    node: Option<&str>,
+    replica_node: Option<&str>,
+) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+    let nodes = NodeFilter::new(node, replica_node)?;
+    Ok(queues.into_iter().filter(|q| nodes.matches(q)).collect())
+}
+
+/// Regex filters of the node of a queue and of the nodes of its replicas, e.g. to clean up after decommissioning a node
+pub(crate) struct NodeFilter {
+    node: Option<Regex>,
+    replica_node: Option<Regex>,
+}
+
+impl NodeFilter {
+    pub(crate) fn new(
+        node: Option<&str>,
+        replica_node: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(NodeFilter {
+            node: node.map(Regex::new).transpose()?,
+            replica_node: replica_node.map(Regex::new).transpose()?,
+        })
+    }
+
+    pub(crate) fn matches(&self, queue: &Queue) -> bool {
+        self.node.as_ref().is_none_or(|f| f.is_match(&queue.node))
+            && self
+                .replica_node
+                .as_ref()
+                .is_none_or(|f| queue.replica_nodes().iter().any(|n| f.is_match(n)))
+    }
+}
+
+fn select_queues_by_name(
+    queues: Vec<Queue>,
+    filter: &str,
+    exclude_queue_filters: &[String],
 ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
     let include_filter = Regex::new(filter)?;
-    let exclude_filters = get_regex_vec(&exclude_queue_filters)?;
+    let exclude_filters = get_regex_vec(exclude_queue_filters)?;
 
-    let queues = rc
-        .list_queues_in(vhost)?
+    let queues = queues
         .into_iter()
         .filter(|queue| {
-            queue.message_count > 0
-                && include_filter.is_match(&queue.name)
+            include_filter.is_match(&queue.name)
                 && exclude_filters.iter().all(|f| !f.is_match(&queue.name))
         })
-        .map(Queue::from)
         .collect();
 
     Ok(queues)
 }
 
+/// Fetches a queue again right before acting on it. Returns the reason to skip the queue
+/// if it was deleted, got consumers or got more than `max_message_increase` new messages since it was collected
+pub fn revalidate_queue(
+    api: &impl ManagementApi,
+    vhost: &str,
+    queue: &Queue,
+    max_message_increase: u64,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(current) = api.queue_info(vhost, &queue.name)? else {
+        return Ok(Some("it no longer exists".to_string()));
+    };
+
+    if queue.consumer_count == 0 && current.consumer_count > 0 {
+        return Ok(Some(format!(
+            "{} consumers appeared",
+            current.consumer_count
+        )));
+    }
+    if current.messages > queue.messages.saturating_add(max_message_increase) {
+        return Ok(Some(format!(
+            "messages increased from {} to {}",
+            queue.messages, current.messages
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Waits until the deadline for a quorum queue without a leader or with offline members to recover, if there is one.
+/// All queues of a run share the deadline of `--wait-for-quorum`, so the wait doesn't grow with their number.
+/// Returns the problem if it didn't recover. In dry run mode the state from the collection is reported without waiting
+pub fn wait_for_quorum(
+    api: &impl ManagementApi,
+    vhost: &str,
+    queue: &Queue,
+    dry_run: bool,
+    deadline: Option<Instant>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut waiting = match QuorumWait::start(queue, dry_run, deadline) {
+        ControlFlow::Break(problem) => return Ok(problem),
+        ControlFlow::Continue(waiting) => waiting,
+    };
+    while let Some(poll) = waiting.next_poll() {
+        std::thread::sleep(poll.saturating_duration_since(Instant::now()));
+        if let ControlFlow::Break(problem) = waiting.update(api.queue_info(vhost, &queue.name)?) {
+            return Ok(problem);
+        }
+    }
+
+    Ok(waiting.problem())
+}
+
+/// State of waiting for a quorum queue to recover, shared by the blocking and the async cleaner. They poll the queue
+/// at `next_poll` and pass it to `update` until either of them ends the wait
+pub(crate) struct QuorumWait {
+    problem: String,
+    deadline: Instant,
+    polled: bool,
+}
+
+impl QuorumWait {
+    /// Ends right away with `None` if the queue is healthy, or with its problem in dry run mode or without a deadline
+    pub fn start(
+        queue: &Queue,
+        dry_run: bool,
+        deadline: Option<Instant>,
+    ) -> ControlFlow<Option<String>, QuorumWait> {
+        match (queue.quorum_problem(), deadline) {
+            (None, _) => ControlFlow::Break(None),
+            (Some(problem), None) => ControlFlow::Break(Some(problem)),
+            (Some(problem), Some(_)) if dry_run => ControlFlow::Break(Some(problem)),
+            (Some(problem), Some(deadline)) => ControlFlow::Continue(QuorumWait {
+                problem,
+                deadline,
+                polled: false,
+            }),
+        }
+    }
+
+    /// When to poll the queue next, `None` once the wait is over. A queue reached after the deadline is polled once,
+    /// it may have recovered while other queues were waited for
+    pub fn next_poll(&self) -> Option<Instant> {
+        let now = Instant::now();
+        (now < self.deadline || !self.polled)
+            .then(|| self.deadline.max(now).min(now + QUORUM_POLL_INTERVAL))
+    }
+
+    /// Takes the state of the queue, `None` if it no longer exists. Ends the wait unless it still has a problem
+    pub fn update(&mut self, current: Option<Queue>) -> ControlFlow<Option<String>> {
+        self.polled = true;
+        let Some(current) = current else {
+            return ControlFlow::Break(Some("it no longer exists".to_string()));
+        };
+        match current.quorum_problem() {
+            Some(problem) => {
+                self.problem = problem;
+                ControlFlow::Continue(())
+            }
+            None => ControlFlow::Break(None),
+        }
+    }
+
+    /// Problem of the queue when the wait is over
+    pub fn problem(self) -> Option<String> {
+        Some(self.problem)
+    }
+}
+
 pub fn collect_objects(
-    rc: &RmqClient,
+    api: &impl ManagementApi,
     vhost: &str,
     options: &DeleteOptions,
 ) -> Result<CollectedObjects, Box<dyn std::error::Error>> {
-    let all_queues: Vec<_> = rc
-        .list_queues_in(vhost)?
-        .into_iter()
-        .map(Queue::from)
-        .collect();
+    select_objects(collect_topology(api, vhost, options)?, options)
+}
+
+/// Fetches the parts of the topology of a virtual host the options need
+pub(crate) fn collect_topology(
+    api: &impl ManagementApi,
+    vhost: &str,
+    options: &DeleteOptions,
+) -> Result<Topology, Box<dyn std::error::Error>> {
+    let mut topology = Topology::default();
+    for request in topology_requests(options) {
+        match request {
+            TopologyRequest::Queues(name_filter) => {
+                topology.queues = api.queues(vhost, name_filter)?;
+            }
+            TopologyRequest::Exchanges => topology.exchanges = api.exchange_names(vhost)?,
+            TopologyRequest::Bindings => topology.bindings = api.bindings(vhost)?,
+            TopologyRequest::Policies => topology.policies = api.policies(vhost, false)?,
+            TopologyRequest::OperatorPolicies => {
+                topology.operator_policies = api.policies(vhost, true)?;
+            }
+        }
+    }
+
+    Ok(topology)
+}
+
+/// Listings the options need to select objects from, queues always come first
+pub(crate) fn topology_requests(options: &DeleteOptions) -> Vec<TopologyRequest<'_>> {
+    let needs = TopologyNeeds::of(options);
+    let mut requests = vec![TopologyRequest::Queues(needs.queue_name_filter(options))];
+    if needs.exchanges {
+        requests.push(TopologyRequest::Exchanges);
+    }
+    if needs.bindings {
+        requests.push(TopologyRequest::Bindings);
+    }
+    if needs.policies {
+        requests.push(TopologyRequest::Policies);
+    }
+    if needs.operator_policies {
+        requests.push(TopologyRequest::OperatorPolicies);
+    }
+    requests
+}
+
+impl TopologyNeeds {
+    fn of(options: &DeleteOptions) -> TopologyNeeds {
+        TopologyNeeds {
+            all_queues: options.exchanges && options.exchanges_without_destination
+                || options.policies,
+            exchanges: options.exchanges || options.policies,
+            bindings: options.exchanges && options.exchanges_without_destination,
+            policies: options.policies,
+            operator_policies: options.policies && options.operator_policies,
+        }
+    }
+
+    /// Name filter for listing queues. The filter is only a hint for the broker, the queues are matched again anyway
+    fn queue_name_filter<'o>(&self, options: &'o DeleteOptions) -> Option<&'o str> {
+        (!self.all_queues).then_some(options.queue_filter.as_str())
+    }
+}
+
+/// Selects objects to delete from the topology of a virtual host
+pub(crate) fn select_objects(
+    topology: Topology,
+    options: &DeleteOptions,
+) -> Result<CollectedObjects, Box<dyn std::error::Error>> {
+    let all_queues = topology.queues;
 
     let queues_to_delete = if options.queues {
         let include_filter = Regex::new(&options.queue_filter)?;
         let exclude_filters = get_regex_vec(&options.exclude_queue_filter)?;
+        let nodes = NodeFilter::new(options.node.as_deref(), options.replica_node.as_deref())?;
         all_queues
             .iter()
             .filter(|queue| {
                 include_filter.is_match(&queue.name)
                     && exclude_filters.iter().all(|f| !f.is_match(&queue.name))
-                    && (!options.queues_without_consumers || queue.consumer_count == 0)
+                    && !options.exclude_queues.contains(&queue.name)
+                    && nodes.matches(queue)
+                    && (!options.queues_without_consumers
+                        || options.force_consumers
+                        || queue.consumer_count == 0)
+                    && (!options.quorum_unhealthy || queue.quorum_problem().is_some())
             })
             .cloned()
             .collect()
@@ -83,8 +450,24 @@ pub fn collect_objects(
         vec![]
     };
 
+    // with --force-consumers queues are selected regardless of consumers, which have to be closed before deleting
+    let queues_with_consumers = if options.force_consumers {
+        queues_to_delete
+            .iter()
+            .filter(|q: &&Queue| !q.exclusive && q.consumer_count > 0)
+            .map(|q| q.name.clone())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let surviving_queues: Vec<_> = all_queues
+        .iter()
+        .filter(|x| x.exclusive || !queues_to_delete.iter().any(|dq| dq.name == x.name))
+        .collect();
+
     let delete_exchanges = if options.exchanges {
-        let skip_exchanges = vec![
+        let skip_exchanges = [
             "",
             "amq.direct",
             "amq.fanout",
@@ -95,20 +478,16 @@ pub fn collect_objects(
             "(AMQP default)",
         ];
 
-        let exchanges = rc
-            .list_exchanges_in(vhost)?
-            .into_iter()
-            .filter(|x| !skip_exchanges.contains(&x.name.as_str()))
-            .map(|x| x.name)
+        let exchanges = topology
+            .exchanges
+            .iter()
+            .filter(|x| !skip_exchanges.contains(&x.as_str()))
+            .cloned()
             .collect();
 
         if options.exchanges_without_destination {
-            let surviving_queues = all_queues
-                .into_iter()
-                .filter(|x| x.exclusive || !queues_to_delete.iter().any(|dq| dq.name == x.name))
-                .map(|x| x.name)
-                .collect();
-            filter_exchanges_without_destination(rc, vhost, exchanges, surviving_queues)?
+            let surviving_queues = surviving_queues.iter().map(|x| x.name.clone()).collect();
+            filter_exchanges_without_destination(topology.bindings, exchanges, surviving_queues)
         } else {
             exchanges
         }
@@ -116,21 +495,134 @@ pub fn collect_objects(
         vec![]
     };
 
+    let mut delete_policies = vec![];
+    let mut policy_warnings = vec![];
+    if options.policies {
+        let surviving_exchanges: Vec<_> = topology
+            .exchanges
+            .into_iter()
+            .filter(|x| !x.is_empty() && !delete_exchanges.contains(x))
+            .collect();
+
+        let policies = [
+            (topology.policies, false),
+            (topology.operator_policies, true),
+        ];
+
+        // user policies and operator policies are applied independently, so they are evaluated separately
+        for (policies, operator) in policies {
+            let unused = filter_unused_policies(
+                policies,
+                &surviving_queues,
+                &surviving_exchanges,
+                &mut policy_warnings,
+            );
+            delete_policies.extend(
+                unused
+                    .into_iter()
+                    .map(|name| CollectedPolicy { name, operator }),
+            );
+        }
+    }
+
     Ok(CollectedObjects {
         queues: queues_to_delete,
+        queues_with_consumers,
         exchanges: delete_exchanges,
+        policies: delete_policies,
+        policy_warnings,
+        unhealthy_quorum_queues: options.quorum_unhealthy,
     })
 }
 
+/// Returns names of policies whose pattern matches none of the given objects.
+/// Policies that can't take effect on some objects because of a policy with a higher or the same priority are reported as warnings
+fn filter_unused_policies(
+    policies: Vec<Policy>,
+    queues: &[&Queue],
+    exchanges: &[ExchangeName],
+    warnings: &mut Vec<String>,
+) -> Vec<PolicyName> {
+    let mut unused = vec![];
+    let mut used: Vec<(Policy, HashSet<String>)> = vec![];
+
+    for policy in policies {
+        let Ok(regex) = Regex::new(&policy.pattern) else {
+            warnings.push(format!(
+                "Pattern '{}' of policy {} can't be evaluated, the policy is kept",
+                policy.pattern, policy.name
+            ));
+            continue;
+        };
+
+        let queue_type = match policy.apply_to {
+            PolicyTarget::ClassicQueues => Some("classic"),
+            PolicyTarget::QuorumQueues => Some("quorum"),
+            PolicyTarget::Streams => Some("stream"),
+            _ => None,
+        };
+        let applies_to_queues = policy.apply_to != PolicyTarget::Exchanges;
+        let applies_to_exchanges =
+            matches!(policy.apply_to, PolicyTarget::Exchanges | PolicyTarget::All);
+
+        let matched_queues = queues
+            .iter()
+            .filter(|_| applies_to_queues)
+            .filter(|q| queue_type.is_none_or(|t| q.queue_type == t))
+            .filter(|q| regex.is_match(&q.name))
+            .map(|q| format!("queue {}", q.name));
+        let matched_exchanges = exchanges
+            .iter()
+            .filter(|_| applies_to_exchanges)
+            .filter(|x| regex.is_match(x))
+            .map(|x| format!("exchange {}", x));
+        let matched: HashSet<_> = matched_queues.chain(matched_exchanges).collect();
+
+        if matched.is_empty() {
+            unused.push(policy.name);
+        } else {
+            used.push((policy, matched));
+        }
+    }
+
+    // only one policy with the highest priority applies to an object
+    for (policy, objects) in &used {
+        for (other, other_objects) in &used {
+            // a pair of policies with the same priority is reported once
+            let same_priority = other.priority == policy.priority && other.name > policy.name;
+            if other.priority <= policy.priority && !same_priority {
+                continue;
+            }
+
+            let overlap = objects.intersection(other_objects).count();
+            if overlap == 0 {
+                continue;
+            }
+            if same_priority {
+                warnings.push(format!(
+                    "Policies {} and {} have the same priority {} and both match {} objects",
+                    policy.name, other.name, policy.priority, overlap
+                ));
+            } else {
+                warnings.push(format!(
+                    "Policy {} with priority {} is overridden by policy {} with priority {} on {} objects",
+                    policy.name, policy.priority, other.name, other.priority, overlap
+                ));
+            }
+        }
+    }
+
+    unused.sort();
+    unused
+}
+
 fn filter_exchanges_without_destination(
-    rc: &RmqClient,
-    vhost: &str,
+    bindings: Vec<Binding>,
     all_exchanges: Vec<ExchangeName>,
     queues: Vec<QueueName>,
-) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>> {
+) -> Vec<ExchangeName> {
     // build a hashmap from binding destination to all sources
-    let bindings: HashMap<(String, BindingDestinationType), Vec<String>> = rc
-        .list_bindings_in(vhost)?
+    let bindings: HashMap<(String, BindingDestinationType), Vec<String>> = bindings
         .into_iter()
         .fold(HashMap::new(), |mut acc, binding| {
             acc.entry((binding.destination, binding.destination_type))
@@ -173,13 +665,177 @@ fn filter_exchanges_without_destination(
         .collect();
     exchanges_to_delete.sort();
 
-    Ok(exchanges_to_delete)
+    exchanges_to_delete
 }
 
-fn get_regex_vec(filters: &Vec<String>) -> Result<Vec<Regex>, Box<dyn std::error::Error>> {
+pub(crate) fn get_regex_vec(filters: &[String]) -> Result<Vec<Regex>, Box<dyn std::error::Error>> {
     let regex_vec = filters
         .iter()
         .map(|f| Regex::new(f))
         .collect::<Result<Vec<_>, _>>()?;
     Ok(regex_vec)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeBroker;
+
+    const VHOST: &str = "test";
+
+    /// ex1  →  ex2  →  ex3
+    ///   🡾            🡽
+    ///     ex4 ------
+    ///       🡾
+    ///         ex5  →  ex6
+    fn complex_topology() -> FakeBroker {
+        let broker = FakeBroker::new();
+        for exchange in ["ex1", "ex2", "ex3", "ex4", "ex5", "ex6"] {
+            broker.add_exchange(VHOST, exchange);
+        }
+        for (source, destination) in [
+            ("ex1", "ex2"),
+            ("ex2", "ex3"),
+            ("ex1", "ex4"),
+            ("ex4", "ex3"),
+            ("ex4", "ex5"),
+            ("ex5", "ex6"),
+        ] {
+            broker.bind(VHOST, source, destination, BindingDestinationType::Exchange);
+        }
+        broker
+    }
+
+    fn bind_queue(broker: &FakeBroker, exchange: &str, queue: &str) {
+        broker.add_queue(
+            VHOST,
+            Queue {
+                name: queue.to_string(),
+                messages: 0,
+                exclusive: false,
+                queue_type: "classic".to_string(),
+                consumer_count: 0,
+                ..Queue::default()
+            },
+        );
+        broker.bind(VHOST, exchange, queue, BindingDestinationType::Queue);
+    }
+
+    fn exchanges_without_destination(
+        broker: &FakeBroker,
+        surviving_queues: &[&str],
+    ) -> Vec<ExchangeName> {
+        filter_exchanges_without_destination(
+            broker.bindings(VHOST).unwrap(),
+            broker.exchange_names(VHOST).unwrap(),
+            surviving_queues.iter().map(|q| q.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn quorum_queues_without_leader_or_with_offline_members_are_unhealthy() {
+        let members = vec!["rabbit@a".to_string(), "rabbit@b".to_string()];
+        let healthy = Queue {
+            name: "q".to_string(),
+            queue_type: "quorum".to_string(),
+            leader: Some("rabbit@a".to_string()),
+            members: members.clone(),
+            online: members.clone(),
+            ..Queue::default()
+        };
+        let electing = Queue {
+            leader: None,
+            ..healthy.clone()
+        };
+        let degraded = Queue {
+            online: vec!["rabbit@a".to_string()],
+            ..healthy.clone()
+        };
+
+        assert_eq!(healthy.quorum_problem(), None);
+        assert!(electing.quorum_problem().is_some());
+        assert_eq!(
+            degraded.quorum_problem().as_deref(),
+            Some("1 of 2 members are offline: rabbit@b")
+        );
+        assert_eq!(Queue::default().quorum_problem(), None);
+    }
+
+    #[test]
+    fn only_queues_matching_the_filter_are_listed_unless_surviving_queues_are_needed() {
+        let mut options = DeleteOptions {
+            queues: true,
+            queue_filter: "^test_".to_string(),
+            ..DeleteOptions::default()
+        };
+        assert_eq!(
+            TopologyNeeds::of(&options).queue_name_filter(&options),
+            Some("^test_")
+        );
+
+        options.exchanges = true;
+        options.exchanges_without_destination = true;
+        assert_eq!(
+            TopologyNeeds::of(&options).queue_name_filter(&options),
+            None
+        );
+
+        options.exchanges_without_destination = false;
+        options.policies = true;
+        assert_eq!(
+            TopologyNeeds::of(&options).queue_name_filter(&options),
+            None
+        );
+    }
+
+    #[test]
+    fn exchanges_without_any_queue_have_no_destination() {
+        let broker = complex_topology();
+
+        assert_eq!(
+            exchanges_without_destination(&broker, &[]),
+            ["ex1", "ex2", "ex3", "ex4", "ex5", "ex6"]
+        );
+    }
+
+    #[test]
+    fn exchanges_routing_to_a_queue_through_other_exchanges_have_destination() {
+        let broker = complex_topology();
+        bind_queue(&broker, "ex6", "queue1");
+
+        assert_eq!(
+            exchanges_without_destination(&broker, &["queue1"]),
+            ["ex2", "ex3"]
+        );
+    }
+
+    #[test]
+    fn exchanges_routing_to_queues_from_several_paths_have_destination() {
+        let broker = complex_topology();
+        bind_queue(&broker, "ex3", "queue1");
+        bind_queue(&broker, "ex6", "queue2");
+
+        assert!(exchanges_without_destination(&broker, &["queue1", "queue2"]).is_empty());
+    }
+
+    #[test]
+    fn exchanges_routing_only_to_deleted_queues_have_no_destination() {
+        let broker = complex_topology();
+        bind_queue(&broker, "ex5", "process-1");
+
+        let options = DeleteOptions {
+            queues: true,
+            queue_filter: "^process-".to_string(),
+            exchanges: true,
+            exchanges_without_destination: true,
+            ..DeleteOptions::default()
+        };
+        let objects = collect_objects(&broker, VHOST, &options).unwrap();
+
+        assert_eq!(objects.queues.len(), 1);
+        assert_eq!(
+            objects.exchanges,
+            ["ex1", "ex2", "ex3", "ex4", "ex5", "ex6"]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use crate::ConnectionsOptions;
+use crate::audit::{AuditedObject, audited};
+use crate::client::RmqClient;
+use crate::collector::{Queue, QueueName};
+use crate::print_line;
+use crate::request::request;
+use chrono::{DateTime, NaiveDateTime};
+use rabbitmq_http_client::responses::{Channel, ChannelState, Connection};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct CollectedConnection {
+    pub connection: Connection,
+    pub channels: Vec<Channel>,
+}
+
+/// Only channels that aren't running report since when they are idle
+#[derive(Deserialize)]
+struct ChannelIdleSince {
+    name: String,
+    #[serde(default)]
+    idle_since: Option<String>,
+}
+
+pub fn collect_connections(
+    rc: &RmqClient,
+    vhost: &str,
+    options: &ConnectionsOptions,
+) -> Result<Vec<CollectedConnection>, Box<dyn std::error::Error>> {
+    let client_name = options.client_name.as_deref().map(Regex::new).transpose()?;
+    let user = options.user.as_deref().map(Regex::new).transpose()?;
+    let peer_host = options.peer_host.as_deref().map(Regex::new).transpose()?;
+    let connected_before = options
+        .connected_for
+        .map(|age| now_millis().saturating_sub(age.as_millis() as u64));
+    let idle_before = options
+        .idle
+        .map(|age| now_millis().saturating_sub(age.as_millis() as u64));
+    let idle_since = match idle_before {
+        Some(_) => list_idle_since(rc, vhost)?,
+        None => HashMap::new(),
+    };
+
+    let channels = request!(rc, rc.list_channels_in(vhost))?;
+
+    let mut connections: Vec<_> = request!(rc, rc.list_connections_in(vhost))?
+        .into_iter()
+        .filter(|c| {
+            client_name
+                .as_ref()
+                .is_none_or(|r| r.is_match(&c.client_properties.connection_name))
+                && user.as_ref().is_none_or(|r| r.is_match(&c.username))
+                && peer_host
+                    .as_ref()
+                    .is_none_or(|r| r.is_match(c.client_hostname.as_deref().unwrap_or_default()))
+                && connected_before.is_none_or(|before| c.connected_at <= before)
+        })
+        .map(|connection| CollectedConnection {
+            channels: channels
+                .iter()
+                .filter(|ch| ch.connection_details.name == connection.name)
+                .cloned()
+                .collect(),
+            connection,
+        })
+        .filter(|c| idle_before.is_none_or(|before| is_idle(c, &idle_since, before)))
+        .collect();
+
+    connections.sort_by_key(|c| c.connection.connected_at);
+    Ok(connections)
+}
+
+/// The management API doesn't report for how long a connection has been idle, so a connection is idle since `before`
+/// when it was opened by then and each of its channels has been idle since then, without consumers
+/// and unacknowledged messages
+fn is_idle(
+    connection: &CollectedConnection,
+    idle_since: &HashMap<String, u64>,
+    before: u64,
+) -> bool {
+    connection.connection.connected_at <= before
+        && connection.channels.iter().all(|ch| {
+            ch.consumer_count == 0
+                && ch.messages_unacknowledged == 0
+                && !matches!(ch.state, ChannelState::Running)
+                && idle_since
+                    .get(&ch.name)
+                    .is_some_and(|since| *since <= before)
+        })
+}
+
+/// Returns since when channels are idle, in Unix milliseconds
+fn list_idle_since(
+    rc: &RmqClient,
+    vhost: &str,
+) -> Result<HashMap<String, u64>, Box<dyn std::error::Error>> {
+    let channels: Vec<ChannelIdleSince> =
+        rc.list_paged(&["vhosts", vhost, "channels"], "name,idle_since", None)?;
+    Ok(channels
+        .into_iter()
+        .filter_map(|ch| Some((ch.name, parse_timestamp(&ch.idle_since?)?)))
+        .collect())
+}
+
+/// Parses a timestamp of the management API, which is either RFC 3339 or, on older versions, "YYYY-MM-DD HH:MM:SS" in UTC
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.timestamp_millis())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc().timestamp_millis())
+        })
+        .ok()
+        .and_then(|millis| u64::try_from(millis).ok())
+}
+
+pub fn print_connections(connections: &[CollectedConnection], with_channels: bool) {
+    for c in connections {
+        let connection = &c.connection;
+        println!(
+            "🔌  Connection {} - user {}, client '{}', peer {}, connected {} ago, {} channels",
+            connection.name,
+            connection.username,
+            connection.client_properties.connection_name,
+            connection.client_hostname.as_deref().unwrap_or("unknown"),
+            format_age(now_millis().saturating_sub(connection.connected_at)),
+            c.channels.len()
+        );
+
+        if with_channels {
+            for channel in &c.channels {
+                println!(
+                    "      channel {} - {}, {} consumers, {} unacknowledged messages",
+                    channel.name,
+                    channel.state,
+                    channel.consumer_count,
+                    channel.messages_unacknowledged
+                );
+            }
+        }
+    }
+}
+
+pub fn close_connections(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    connections: &[CollectedConnection],
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for c in connections {
+        print_line(
+            dry_run,
+            &format!("Closing connection {}", c.connection.name),
+        );
+        let object = AuditedObject::connection(&c.connection.name);
+        audited(rc, vhost, "close", object, dry_run, || {
+            Ok(request!(
+                rc,
+                rc.close_connection(&c.connection.name, Some(reason), true)
+            )?)
+        })?;
+    }
+
+    println!("Closed {} connections", connections.len());
+
+    Ok(())
+}
+
+/// Closing is asynchronous, so waits until the closed connections disappear from the listing
+/// to let dependent objects like exclusive queues and consumers go away too
+pub fn wait_for_closed_connections(
+    rc: &RmqClient,
+    vhost: &str,
+    connections: &[CollectedConnection],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for _ in 0..10 {
+        let open = request!(rc, rc.list_connections_in(vhost))?;
+        if !connections
+            .iter()
+            .any(|c| open.iter().any(|o| o.name == c.connection.name))
+        {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    Err("Closed connections are still open after waiting".into())
+}
+
+/// Finds connections that have consumers on the given queues
+pub fn collect_consumer_connections(
+    rc: &RmqClient,
+    vhost: &str,
+    queues: &[QueueName],
+) -> Result<Vec<CollectedConnection>, Box<dyn std::error::Error>> {
+    let connection_names: HashSet<_> = request!(rc, rc.list_consumers_in(vhost))?
+        .into_iter()
+        .filter(|c| queues.contains(&c.queue.name))
+        .filter_map(|c| c.channel_details.map(|ch| ch.connection_name))
+        .collect();
+
+    let channels = request!(rc, rc.list_channels_in(vhost))?;
+    let connections = request!(rc, rc.list_connections_in(vhost))?
+        .into_iter()
+        .filter(|c| connection_names.contains(&c.name))
+        .map(|connection| CollectedConnection {
+            channels: channels
+                .iter()
+                .filter(|ch| ch.connection_details.name == connection.name)
+                .cloned()
+                .collect(),
+            connection,
+        })
+        .collect();
+
+    Ok(connections)
+}
+
+/// Returns other queues the given connections consume from. Closing the connections cancels those consumers too
+pub fn list_other_consumed_queues(
+    rc: &RmqClient,
+    vhost: &str,
+    connections: &[CollectedConnection],
+    queues: &[QueueName],
+) -> Result<Vec<QueueName>, Box<dyn std::error::Error>> {
+    let mut other: Vec<_> = request!(rc, rc.list_consumers_in(vhost))?
+        .into_iter()
+        .filter(|c| {
+            c.channel_details.as_ref().is_some_and(|ch| {
+                connections
+                    .iter()
+                    .any(|cc| cc.connection.name == ch.connection_name)
+            })
+        })
+        .map(|c| c.queue.name)
+        .filter(|name| !queues.contains(name))
+        .collect();
+    other.sort();
+    other.dedup();
+    Ok(other)
+}
+
+/// Changes the queues to how they are after closing the connections: exclusive queues consumed on them are gone
+/// and their consumers no longer count
+pub fn expect_closed(
+    rc: &RmqClient,
+    vhost: &str,
+    connections: &[CollectedConnection],
+    queues: &mut Vec<Queue>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if connections.is_empty() {
+        return Ok(());
+    }
+
+    let mut closed_consumers: HashMap<QueueName, u16> = HashMap::new();
+    for consumer in request!(rc, rc.list_consumers_in(vhost))? {
+        if consumer.channel_details.is_some_and(|ch| {
+            connections
+                .iter()
+                .any(|c| c.connection.name == ch.connection_name)
+        }) {
+            *closed_consumers.entry(consumer.queue.name).or_default() += 1;
+        }
+    }
+
+    queues.retain(|q| !(q.exclusive && closed_consumers.contains_key(&q.name)));
+    for queue in queues {
+        if let Some(closed) = closed_consumers.get(&queue.name) {
+            queue.consumer_count = queue.consumer_count.saturating_sub(*closed);
+        }
+    }
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+pub(crate) fn format_age(millis: u64) -> String {
+    let seconds = millis / 1000;
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
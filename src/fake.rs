@@ -0,0 +1,195 @@
+// without the testing feature only the unit tests use the broker, and not all of it
+#![cfg_attr(not(feature = "testing"), allow(dead_code))]
+
+use crate::collector::{ExchangeName, Queue};
+use crate::management::{Binding, ManagementApi};
+use rabbitmq_http_client::commons::BindingDestinationType;
+use rabbitmq_http_client::responses::Policy;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// In-memory broker for deterministic tests of collectors and actions without a running RabbitMQ
+#[derive(Default)]
+pub struct FakeBroker {
+    vhosts: Mutex<BTreeMap<String, FakeVhost>>,
+}
+
+#[derive(Default)]
+struct FakeVhost {
+    queues: Vec<Queue>,
+    exchanges: Vec<ExchangeName>,
+    bindings: Vec<Binding>,
+    policies: Vec<Policy>,
+    operator_policies: Vec<Policy>,
+}
+
+impl FakeBroker {
+    pub fn new() -> Self {
+        FakeBroker::default()
+    }
+
+    pub fn add_queue(&self, vhost: &str, queue: Queue) {
+        self.with_vhost(vhost, |v| v.queues.push(queue));
+    }
+
+    pub fn add_exchange(&self, vhost: &str, exchange: &str) {
+        self.with_vhost(vhost, |v| v.exchanges.push(exchange.to_string()));
+    }
+
+    /// Binds a queue or an exchange to the source exchange
+    pub fn bind(
+        &self,
+        vhost: &str,
+        source: &str,
+        destination: &str,
+        destination_type: BindingDestinationType,
+    ) {
+        self.with_vhost(vhost, |v| {
+            v.bindings.push(Binding {
+                source: source.to_string(),
+                destination: destination.to_string(),
+                destination_type,
+            })
+        });
+    }
+
+    pub fn add_policy(&self, vhost: &str, policy: Policy, operator: bool) {
+        self.with_vhost(vhost, |v| {
+            if operator {
+                v.operator_policies.push(policy);
+            } else {
+                v.policies.push(policy);
+            }
+        });
+    }
+
+    pub fn queue(&self, vhost: &str, name: &str) -> Option<Queue> {
+        self.with_vhost(vhost, |v| v.queues.iter().find(|q| q.name == name).cloned())
+    }
+
+    pub fn exchange_exists(&self, vhost: &str, name: &str) -> bool {
+        self.with_vhost(vhost, |v| v.exchanges.iter().any(|x| x == name))
+    }
+
+    fn with_vhost<T>(&self, vhost: &str, f: impl FnOnce(&mut FakeVhost) -> T) -> T {
+        let mut vhosts = self.vhosts.lock().unwrap_or_else(|e| e.into_inner());
+        f(vhosts.entry(vhost.to_string()).or_default())
+    }
+
+    fn existing_vhost<T>(
+        &self,
+        vhost: &str,
+        f: impl FnOnce(&mut FakeVhost) -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let mut vhosts = self.vhosts.lock().unwrap_or_else(|e| e.into_inner());
+        let vhost = vhosts
+            .get_mut(vhost)
+            .ok_or_else(|| format!("Virtual host '{}' doesn't exist", vhost))?;
+        f(vhost)
+    }
+}
+
+impl ManagementApi for FakeBroker {
+    fn queues(
+        &self,
+        vhost: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        let filter = name_filter.map(Regex::new).transpose()?;
+        self.existing_vhost(vhost, |v| {
+            Ok(v.queues
+                .iter()
+                .filter(|q| filter.as_ref().is_none_or(|f| f.is_match(&q.name)))
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn exchange_names(&self, vhost: &str) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| Ok(v.exchanges.clone()))
+    }
+
+    fn bindings(&self, vhost: &str) -> Result<Vec<Binding>, Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| Ok(v.bindings.clone()))
+    }
+
+    fn policies(
+        &self,
+        vhost: &str,
+        operator: bool,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            Ok(if operator {
+                v.operator_policies.clone()
+            } else {
+                v.policies.clone()
+            })
+        })
+    }
+
+    fn queue_info(
+        &self,
+        vhost: &str,
+        queue: &str,
+    ) -> Result<Option<Queue>, Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            Ok(v.queues.iter().find(|q| q.name == queue).cloned())
+        })
+    }
+
+    fn purge(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            let queue = v
+                .queues
+                .iter_mut()
+                .find(|q| q.name == queue)
+                .ok_or_else(|| format!("Queue '{}' doesn't exist", queue))?;
+            queue.messages = 0;
+            Ok(())
+        })
+    }
+
+    fn remove_queue(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            v.queues.retain(|q| q.name != queue);
+            v.bindings.retain(|b| {
+                b.destination_type != BindingDestinationType::Queue || b.destination != queue
+            });
+            Ok(())
+        })
+    }
+
+    fn remove_exchange(
+        &self,
+        vhost: &str,
+        exchange: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            v.exchanges.retain(|x| x != exchange);
+            v.bindings.retain(|b| {
+                b.source != exchange
+                    && (b.destination_type != BindingDestinationType::Exchange
+                        || b.destination != exchange)
+            });
+            Ok(())
+        })
+    }
+
+    fn remove_policy(
+        &self,
+        vhost: &str,
+        policy: &str,
+        operator: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.existing_vhost(vhost, |v| {
+            let policies = if operator {
+                &mut v.operator_policies
+            } else {
+                &mut v.policies
+            };
+            policies.retain(|p| p.name != policy);
+            Ok(())
+        })
+    }
+}
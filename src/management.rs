@@ -0,0 +1,200 @@
+use crate::client::RmqClient;
+use crate::collector::{ExchangeName, Queue};
+use crate::request::request;
+use rabbitmq_http_client::commons::BindingDestinationType;
+use rabbitmq_http_client::error::Error;
+use rabbitmq_http_client::responses::Policy;
+
+#[derive(Clone)]
+pub struct Binding {
+    pub source: ExchangeName,
+    pub destination: String,
+    pub destination_type: BindingDestinationType,
+}
+
+/// Operations of the management API the collectors and actions rely on. Implemented by the client of a real broker
+/// and by `FakeBroker` of the `testing` feature, so topology logic can be tested without one
+pub trait ManagementApi {
+    /// Queues of the virtual host. The name filter may be applied by the broker, so callers still apply it themselves
+    fn queues(
+        &self,
+        vhost: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>>;
+
+    fn exchange_names(&self, vhost: &str) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>>;
+
+    fn bindings(&self, vhost: &str) -> Result<Vec<Binding>, Box<dyn std::error::Error>>;
+
+    fn policies(
+        &self,
+        vhost: &str,
+        operator: bool,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error>>;
+
+    /// Current state of a queue, `None` if it no longer exists
+    fn queue_info(
+        &self,
+        vhost: &str,
+        queue: &str,
+    ) -> Result<Option<Queue>, Box<dyn std::error::Error>>;
+
+    fn purge(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn remove_queue(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn remove_exchange(
+        &self,
+        vhost: &str,
+        exchange: &str,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn remove_policy(
+        &self,
+        vhost: &str,
+        policy: &str,
+        operator: bool,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Lets the command line build a `Cleaner` on the client it keeps using
+impl<T: ManagementApi + ?Sized> ManagementApi for &T {
+    fn queues(
+        &self,
+        vhost: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        (**self).queues(vhost, name_filter)
+    }
+
+    fn exchange_names(&self, vhost: &str) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>> {
+        (**self).exchange_names(vhost)
+    }
+
+    fn bindings(&self, vhost: &str) -> Result<Vec<Binding>, Box<dyn std::error::Error>> {
+        (**self).bindings(vhost)
+    }
+
+    fn policies(
+        &self,
+        vhost: &str,
+        operator: bool,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error>> {
+        (**self).policies(vhost, operator)
+    }
+
+    fn queue_info(
+        &self,
+        vhost: &str,
+        queue: &str,
+    ) -> Result<Option<Queue>, Box<dyn std::error::Error>> {
+        (**self).queue_info(vhost, queue)
+    }
+
+    fn purge(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).purge(vhost, queue)
+    }
+
+    fn remove_queue(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).remove_queue(vhost, queue)
+    }
+
+    fn remove_exchange(
+        &self,
+        vhost: &str,
+        exchange: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).remove_exchange(vhost, exchange)
+    }
+
+    fn remove_policy(
+        &self,
+        vhost: &str,
+        policy: &str,
+        operator: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).remove_policy(vhost, policy, operator)
+    }
+}
+
+impl ManagementApi for RmqClient<'_> {
+    fn queues(
+        &self,
+        vhost: &str,
+        name_filter: Option<&str>,
+    ) -> Result<Vec<Queue>, Box<dyn std::error::Error>> {
+        self.list_queues_paged(vhost, name_filter)
+    }
+
+    fn exchange_names(&self, vhost: &str) -> Result<Vec<ExchangeName>, Box<dyn std::error::Error>> {
+        self.list_exchange_names_paged(vhost)
+    }
+
+    fn bindings(&self, vhost: &str) -> Result<Vec<Binding>, Box<dyn std::error::Error>> {
+        let bindings = request!(self, self.list_bindings_in(vhost))?;
+        Ok(bindings
+            .into_iter()
+            .map(|binding| Binding {
+                source: binding.source,
+                destination: binding.destination,
+                destination_type: binding.destination_type,
+            })
+            .collect())
+    }
+
+    fn policies(
+        &self,
+        vhost: &str,
+        operator: bool,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error>> {
+        if operator {
+            Ok(request!(self, self.list_operator_policies_in(vhost))?)
+        } else {
+            Ok(request!(self, self.list_policies_in(vhost))?)
+        }
+    }
+
+    fn queue_info(
+        &self,
+        vhost: &str,
+        queue: &str,
+    ) -> Result<Option<Queue>, Box<dyn std::error::Error>> {
+        match request!(self, self.get_queue_info(vhost, queue)) {
+            Ok(info) => Ok(Some(Queue::from(info))),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn purge(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(request!(self, self.purge_queue(vhost, queue))?)
+    }
+
+    fn remove_queue(&self, vhost: &str, queue: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(request!(self, self.delete_queue(vhost, queue, true))?)
+    }
+
+    fn remove_exchange(
+        &self,
+        vhost: &str,
+        exchange: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(request!(self, self.delete_exchange(vhost, exchange, true))?)
+    }
+
+    fn remove_policy(
+        &self,
+        vhost: &str,
+        policy: &str,
+        operator: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if operator {
+            Ok(request!(
+                self,
+                self.delete_operator_policy(vhost, policy, true)
+            )?)
+        } else {
+            Ok(request!(self, self.delete_policy(vhost, policy, true))?)
+        }
+    }
+}
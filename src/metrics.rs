@@ -0,0 +1,247 @@
+use crate::cleaner::{ActionResult, ObjectKind};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    MessagesPurged,
+    QueuesDeleted,
+    ExchangesDeleted,
+    Failures,
+}
+
+const COUNTERS: [Counter; 4] = [
+    Counter::MessagesPurged,
+    Counter::QueuesDeleted,
+    Counter::ExchangesDeleted,
+    Counter::Failures,
+];
+
+impl Counter {
+    fn name(self) -> &'static str {
+        match self {
+            Counter::MessagesPurged => "clean_rmq_messages_purged_total",
+            Counter::QueuesDeleted => "clean_rmq_queues_deleted_total",
+            Counter::ExchangesDeleted => "clean_rmq_exchanges_deleted_total",
+            Counter::Failures => "clean_rmq_failures_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::MessagesPurged => "Messages removed from queues",
+            Counter::QueuesDeleted => "Deleted queues",
+            Counter::ExchangesDeleted => "Deleted exchanges",
+            Counter::Failures => "Runs that ended with an error",
+        }
+    }
+}
+
+/// Vhost and rule the metrics are labelled with
+type Labels = (String, String);
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<(Counter, Labels), u64>,
+    durations: BTreeMap<Labels, f64>,
+}
+
+/// Metrics of the runs of a client, served by the HTTP listener and written to the node-exporter textfile
+#[derive(Default)]
+pub struct Metrics {
+    /// Shared with the listener thread
+    registry: Arc<Mutex<Registry>>,
+    /// Labels of the run in progress, see `observe`. Runs don't overlap, their parallel work shares the labels
+    current: Mutex<Labels>,
+    textfile: Option<PathBuf>,
+}
+
+impl Metrics {
+    pub fn new(textfile: Option<PathBuf>) -> Metrics {
+        Metrics {
+            textfile,
+            ..Metrics::default()
+        }
+    }
+
+    /// Starts the HTTP listener
+    pub fn listen(&self, listen: Option<SocketAddr>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(addr) = listen {
+            let listener = TcpListener::bind(addr)?;
+            let registry = self.registry.clone();
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    // every request gets the metrics, whatever the path
+                    let _ = stream.read(&mut [0; 1024]);
+                    let body = render(&lock(&registry));
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Runs an action on behalf of a rule. Counters added during the run are labelled with the vhost and the rule,
+    /// a failed run increments the failures counter
+    pub fn observe<T>(
+        &self,
+        vhost: &str,
+        rule: &str,
+        f: impl FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let labels = (vhost.to_string(), rule.to_string());
+        lock(&self.registry).start(&labels);
+        let previous = std::mem::replace(&mut *lock(&self.current), labels.clone());
+
+        let started = Instant::now();
+        let result = f();
+        *lock(&self.current) = previous;
+
+        lock(&self.registry).finish(labels, result.is_err(), started.elapsed());
+        result
+    }
+
+    /// Records a purge or a delete of a [`Cleaner`](crate::Cleaner) or an `AsyncCleaner`, labelled with the vhost
+    /// and the action, and flushes the textfile. Objects that failed count as one failed run, dry runs aren't recorded
+    pub(crate) fn record(
+        &self,
+        vhost: &str,
+        action: &str,
+        result: &ActionResult,
+        elapsed: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if result.dry_run {
+            return Ok(());
+        }
+
+        let labels = (vhost.to_string(), action.to_string());
+        let added = if action == "purge" {
+            vec![(Counter::MessagesPurged, result.messages())]
+        } else {
+            vec![
+                (
+                    Counter::QueuesDeleted,
+                    result.done(ObjectKind::Queue) as u64,
+                ),
+                (
+                    Counter::ExchangesDeleted,
+                    result.done(ObjectKind::Exchange) as u64,
+                ),
+            ]
+        };
+        {
+            let mut registry = lock(&self.registry);
+            registry.start(&labels);
+            for (counter, value) in added {
+                *registry
+                    .counters
+                    .entry((counter, labels.clone()))
+                    .or_default() += value;
+            }
+            registry.finish(labels, result.failed() > 0, elapsed);
+        }
+
+        self.flush()
+    }
+
+    pub fn add(&self, counter: Counter, value: u64) {
+        let key = (counter, lock(&self.current).clone());
+        *lock(&self.registry).counters.entry(key).or_default() += value;
+    }
+
+    /// Writes the node-exporter textfile if one is configured. The file is replaced atomically,
+    /// so the exporter never reads a half-written file
+    pub fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(path) = &self.textfile else {
+            return Ok(());
+        };
+
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, render(&lock(&self.registry)))?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+impl Registry {
+    /// Counters are exposed with 0 from the first run on, so rates can be computed right away
+    fn start(&mut self, labels: &Labels) {
+        for counter in COUNTERS {
+            self.counters.entry((counter, labels.clone())).or_default();
+        }
+    }
+
+    fn finish(&mut self, labels: Labels, failed: bool, elapsed: Duration) {
+        if failed {
+            *self
+                .counters
+                .entry((Counter::Failures, labels.clone()))
+                .or_default() += 1;
+        }
+        self.durations.insert(labels, elapsed.as_secs_f64());
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Metrics in Prometheus text exposition format
+fn render(registry: &Registry) -> String {
+    let mut out = String::new();
+
+    for counter in COUNTERS {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} counter\n",
+            counter.name(),
+            counter.help(),
+            counter.name()
+        ));
+        for ((_, labels), value) in registry.counters.iter().filter(|((c, _), _)| *c == counter) {
+            out.push_str(&format!(
+                "{}{} {}\n",
+                counter.name(),
+                format_labels(labels),
+                value
+            ));
+        }
+    }
+
+    out.push_str("# HELP clean_rmq_cycle_duration_seconds Duration of the last run\n");
+    out.push_str("# TYPE clean_rmq_cycle_duration_seconds gauge\n");
+    for (labels, seconds) in &registry.durations {
+        out.push_str(&format!(
+            "clean_rmq_cycle_duration_seconds{} {}\n",
+            format_labels(labels),
+            seconds
+        ));
+    }
+
+    out
+}
+
+fn format_labels((vhost, rule): &Labels) -> String {
+    format!(
+        "{{vhost=\"{}\",rule=\"{}\"}}",
+        escape_label(vhost),
+        escape_label(rule)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,77 @@
+use crate::client::{Node, RmqClient};
+use crate::connections::format_age;
+use std::collections::BTreeMap;
+
+pub struct CollectedNode {
+    pub name: String,
+    /// Missing if the node hosts queues but isn't a member of the cluster anymore
+    pub node: Option<Node>,
+    /// Queues of the vhost hosted by the node, led by it for quorum queues and streams
+    pub queues: usize,
+    /// Queues of the vhost with a replica on the node
+    pub replicas: usize,
+}
+
+/// Collects nodes of the cluster together with nodes that queues of the vhost still refer to
+pub fn collect_nodes(
+    rc: &RmqClient,
+    vhost: &str,
+) -> Result<Vec<CollectedNode>, Box<dyn std::error::Error>> {
+    let mut nodes: BTreeMap<String, CollectedNode> = rc
+        .list_cluster_nodes()?
+        .into_iter()
+        .map(|node| {
+            let collected = CollectedNode {
+                name: node.name.clone(),
+                node: Some(node),
+                queues: 0,
+                replicas: 0,
+            };
+            (collected.name.clone(), collected)
+        })
+        .collect();
+
+    for queue in rc.list_queues_paged(vhost, None)? {
+        if !queue.node.is_empty() {
+            node_entry(&mut nodes, &queue.node).queues += 1;
+        }
+        for replica in queue.replica_nodes() {
+            if !replica.is_empty() {
+                node_entry(&mut nodes, replica).replicas += 1;
+            }
+        }
+    }
+
+    Ok(nodes.into_values().collect())
+}
+
+fn node_entry<'a>(
+    nodes: &'a mut BTreeMap<String, CollectedNode>,
+    name: &str,
+) -> &'a mut CollectedNode {
+    nodes
+        .entry(name.to_string())
+        .or_insert_with(|| CollectedNode {
+            name: name.to_string(),
+            node: None,
+            queues: 0,
+            replicas: 0,
+        })
+}
+
+pub fn print_nodes(nodes: &[CollectedNode]) {
+    for n in nodes {
+        let state = match &n.node {
+            None => "not in the cluster".to_string(),
+            Some(node) if !node.running => "down".to_string(),
+            Some(node) if node.being_drained => {
+                format!("running for {}, being drained", format_age(node.uptime))
+            }
+            Some(node) => format!("running for {}", format_age(node.uptime)),
+        };
+        println!(
+            "🖥️  Node {} - {}, hosts {} queues, {} replicas",
+            n.name, state, n.queues, n.replicas
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Runs `work` for every item on up to `concurrency` threads and passes the results to `report` on the calling thread
+/// in the order of the items, so the output doesn't depend on which request finishes first.
+/// After the first failed item no new items are started unless `keep_going` is set. The ones already running are
+/// finished and reported too
+pub fn for_each_parallel<T: Sync, V: Send>(
+    items: &[T],
+    concurrency: u16,
+    keep_going: bool,
+    work: impl Fn(&T) -> Result<V, String> + Sync,
+    mut report: impl FnMut(&T, Result<V, String>),
+) {
+    if concurrency <= 1 {
+        for item in items {
+            let result = work(item);
+            let failed = result.is_err();
+            report(item, result);
+            if failed && !keep_going {
+                return;
+            }
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..usize::from(concurrency).min(items.len()) {
+            let sender = sender.clone();
+            let (next, stop, work) = (&next, &stop, &work);
+            scope.spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let result = work(item);
+                    if result.is_err() && !keep_going {
+                        stop.store(true, Ordering::SeqCst);
+                    }
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // items are started in order, so every started item is reported before the receiver runs dry
+        let mut pending = BTreeMap::new();
+        let mut next_report = 0;
+        for (i, result) in receiver {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&next_report) {
+                report(&items[next_report], result);
+                next_report += 1;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn more_items_than_fit_in_the_concurrency_type_are_all_run() {
+        let items: Vec<usize> = (0..usize::from(u16::MAX) + 1).collect();
+        let mut reported = 0;
+        for_each_parallel(&items, 4, false, |i| Ok(*i), |_, _| reported += 1);
+
+        assert_eq!(reported, items.len());
+    }
+}
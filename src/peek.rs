@@ -0,0 +1,122 @@
+use crate::PeekOptions;
+use crate::client::RmqClient;
+use crate::collector::Queue;
+use crate::request::request;
+use rabbitmq_http_client::responses::GetMessage;
+use serde_json::{Value, json};
+
+pub fn peek(
+    rc: &RmqClient,
+    vhost: &str,
+    options: &PeekOptions,
+    queues: &[Queue],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut peeked_queues = vec![];
+    // messages of streams can't be fetched with a get, they're read by consumers with an offset
+    let (streams, queues): (Vec<_>, Vec<_>) = queues.iter().partition(|q| q.is_stream());
+
+    for queue in &queues {
+        // "ack_requeue_true" puts every fetched message back into the queue
+        let messages: Vec<_> = request!(
+            rc,
+            rc.get_messages(vhost, &queue.name, options.count, "ack_requeue_true")
+        )?
+        .into_iter()
+        .map(|message| to_json(message, options.max_payload_length))
+        .collect();
+
+        if options.json {
+            peeked_queues.push(json!({
+                "queue": queue.name,
+                "messages_in_queue": queue.messages,
+                "messages": messages,
+            }));
+        } else {
+            print_queue(queue, &messages);
+        }
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&peeked_queues)?);
+    } else {
+        for stream in &streams {
+            println!(
+                "⏭️  Skipping stream queue {} - streams can't be peeked",
+                stream.name
+            );
+        }
+        println!(
+            "Peeked into {} queues, skipped {} stream queues",
+            queues.len(),
+            streams.len()
+        );
+    }
+
+    Ok(())
+}
+
+fn to_json(message: GetMessage, max_payload_length: usize) -> Value {
+    let mut properties = message.properties.0;
+    let headers = properties.remove("headers").unwrap_or(Value::Null);
+    let encoding = detect_encoding(&message.payload, &message.payload_encoding);
+    let (payload, truncated) = truncate(message.payload, max_payload_length);
+
+    json!({
+        "exchange": message.exchange,
+        "routing_key": message.routing_key,
+        "redelivered": message.redelivered,
+        "properties": properties,
+        "headers": headers,
+        "payload_bytes": message.payload_bytes,
+        "payload_encoding": encoding,
+        "payload_truncated": truncated,
+        "payload": payload,
+    })
+}
+
+/// The management API returns payloads either as a "string" or, when they aren't valid UTF-8, as "base64".
+/// String payloads are further checked for being JSON documents
+fn detect_encoding(payload: &str, payload_encoding: &str) -> &'static str {
+    match payload_encoding {
+        "base64" => "base64",
+        _ if serde_json::from_str::<Value>(payload).is_ok() => "json",
+        _ => "string",
+    }
+}
+
+fn truncate(payload: String, max_length: usize) -> (String, bool) {
+    if max_length == 0 || payload.chars().count() <= max_length {
+        (payload, false)
+    } else {
+        (payload.chars().take(max_length).collect(), true)
+    }
+}
+
+fn print_queue(queue: &Queue, messages: &[Value]) {
+    println!("📬  Queue {} - {}", queue.name, queue.messages);
+
+    for (i, message) in messages.iter().enumerate() {
+        println!(
+            "  #{} exchange '{}', routing key '{}', redelivered: {}",
+            i + 1,
+            message["exchange"].as_str().unwrap_or_default(),
+            message["routing_key"].as_str().unwrap_or_default(),
+            message["redelivered"]
+        );
+        println!("     properties: {}", message["properties"]);
+        if !message["headers"].is_null() {
+            println!("     headers: {}", message["headers"]);
+        }
+        println!(
+            "     payload ({}, {} bytes{}): {}",
+            message["payload_encoding"].as_str().unwrap_or_default(),
+            message["payload_bytes"],
+            if message["payload_truncated"] == true {
+                ", truncated"
+            } else {
+                ""
+            },
+            message["payload"].as_str().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::client::RmqClient;
+use crate::collector::{CollectedObjects, Queue, collect_objects};
+use crate::request::request;
+use crate::{PlanOptions, confirm_changes, delete};
+use rabbitmq_http_client::commons::BindingDestinationType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const PLAN_VERSION: u32 = 1;
+
+/// Objects to delete as collected by the plan command, executed later by `apply <PLAN>`
+#[derive(Serialize, Deserialize)]
+struct Plan {
+    version: u32,
+    created_at: String,
+    broker: Broker,
+    vhost: String,
+    concurrency: u16,
+    objects: CollectedObjects,
+    fingerprints: Fingerprints,
+}
+
+/// Identity of the broker a plan is made for
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Broker {
+    endpoint: String,
+    cluster_name: String,
+}
+
+/// Hashes of the state of objects by name, to find out which objects changed since planning
+#[derive(Serialize, Deserialize, Default)]
+struct Fingerprints {
+    queues: BTreeMap<String, String>,
+    exchanges: BTreeMap<String, String>,
+    policies: BTreeMap<String, String>,
+    operator_policies: BTreeMap<String, String>,
+}
+
+/// State of an exchange including its bindings, as a new binding may give it a destination
+#[derive(Serialize, Deserialize)]
+struct ExchangeState {
+    name: String,
+    #[serde(default, rename = "type")]
+    exchange_type: String,
+    #[serde(default)]
+    durable: bool,
+    #[serde(default)]
+    auto_delete: bool,
+    #[serde(default)]
+    internal: bool,
+    #[serde(default)]
+    arguments: serde_json::Value,
+    #[serde(default)]
+    bindings: Vec<String>,
+}
+
+impl Broker {
+    fn of(rc: &RmqClient) -> Result<Broker, Box<dyn std::error::Error>> {
+        Ok(Broker {
+            endpoint: rc.endpoint().to_string(),
+            cluster_name: request!(rc, rc.overview())?.cluster_name,
+        })
+    }
+}
+
+impl Fingerprints {
+    /// Fingerprints the given queues and, if requested, all exchanges and policies of the virtual host
+    fn of(
+        rc: &RmqClient,
+        vhost: &str,
+        queues: &[Queue],
+        exchanges: bool,
+        policies: bool,
+    ) -> Result<Fingerprints, Box<dyn std::error::Error>> {
+        let mut fingerprints = Fingerprints::default();
+        for queue in queues {
+            fingerprints
+                .queues
+                .insert(queue.name.clone(), fingerprint(queue)?);
+        }
+
+        if exchanges {
+            let mut states: BTreeMap<String, ExchangeState> = rc
+                .list_paged::<ExchangeState>(
+                    &["exchanges", vhost],
+                    "name,type,durable,auto_delete,internal,arguments",
+                    None,
+                )?
+                .into_iter()
+                .map(|state| (state.name.clone(), state))
+                .collect();
+            for binding in request!(rc, rc.list_bindings_in(vhost))? {
+                let line = format!(
+                    "{} -> {:?} {} '{}'",
+                    binding.source,
+                    binding.destination_type,
+                    binding.destination,
+                    binding.routing_key
+                );
+                if binding.destination_type == BindingDestinationType::Exchange
+                    && let Some(state) = states.get_mut(&binding.destination)
+                {
+                    state.bindings.push(line.clone());
+                }
+                if let Some(state) = states.get_mut(&binding.source) {
+                    state.bindings.push(line);
+                }
+            }
+            for (name, mut state) in states {
+                state.bindings.sort();
+                fingerprints.exchanges.insert(name, fingerprint(&state)?);
+            }
+        }
+
+        if policies {
+            for policy in request!(rc, rc.list_policies_in(vhost))? {
+                fingerprints
+                    .policies
+                    .insert(policy.name.clone(), fingerprint(&policy)?);
+            }
+            for policy in request!(rc, rc.list_operator_policies_in(vhost))? {
+                fingerprints
+                    .operator_policies
+                    .insert(policy.name.clone(), fingerprint(&policy)?);
+            }
+        }
+
+        Ok(fingerprints)
+    }
+
+    /// Keeps fingerprints of the planned objects only
+    fn retain(&mut self, objects: &CollectedObjects) {
+        self.queues
+            .retain(|name, _| objects.queues.iter().any(|q| &q.name == name));
+        self.exchanges
+            .retain(|name, _| objects.exchanges.contains(name));
+        self.policies.retain(|name, _| {
+            objects
+                .policies
+                .iter()
+                .any(|p| !p.operator && &p.name == name)
+        });
+        self.operator_policies.retain(|name, _| {
+            objects
+                .policies
+                .iter()
+                .any(|p| p.operator && &p.name == name)
+        });
+    }
+}
+
+/// FNV-1a hash of the JSON representation of the state. Keys of JSON objects are sorted, so it's stable between runs
+fn fingerprint(state: &impl Serialize) -> Result<String, Box<dyn std::error::Error>> {
+    let json = serde_json::to_value(state)?.to_string();
+    let hash = json.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
+
+/// Collects objects like the delete command, prints them and writes them to the plan file. Nothing is changed
+pub fn write_plan(
+    rc: &RmqClient,
+    vhost: &str,
+    options: &PlanOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let delete_options = &options.delete;
+    if delete_options.close_connections.is_some()
+        || delete_options.force_consumers
+        || delete_options.revalidate
+    {
+        return Err(
+            "--close-connections, --force-consumers and --revalidate can't be used in a plan"
+                .into(),
+        );
+    }
+
+    let objects = collect_objects(rc, vhost, delete_options)?;
+    rc.limits
+        .check(true, "delete", &objects.queues, objects.exchanges.len())?;
+    delete(rc, vhost, true, &objects, 1, None)?;
+
+    let mut fingerprints = Fingerprints::of(
+        rc,
+        vhost,
+        &objects.queues,
+        !objects.exchanges.is_empty(),
+        !objects.policies.is_empty(),
+    )?;
+    fingerprints.retain(&objects);
+
+    let plan = Plan {
+        version: PLAN_VERSION,
+        created_at: chrono::Local::now().to_rfc3339(),
+        broker: Broker::of(rc)?,
+        vhost: vhost.to_string(),
+        concurrency: delete_options.concurrency,
+        objects,
+        fingerprints,
+    };
+    std::fs::write(&options.out, serde_json::to_string_pretty(&plan)?)?;
+    println!(
+        "Plan written to {}, apply it with 'clean_rmq apply {}'",
+        options.out.display(),
+        options.out.display()
+    );
+
+    Ok(())
+}
+
+/// Executes exactly the objects of a plan file. Objects that changed or disappeared since planning are skipped
+pub fn apply_plan(
+    rc: &RmqClient,
+    dry_run: bool,
+    yes: bool,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let plan: Plan = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| format!("Plan file {} can't be read: {}", path.display(), e))?;
+    if plan.version != PLAN_VERSION {
+        return Err(format!(
+            "Plan file version {} isn't supported, make a new plan",
+            plan.version
+        )
+        .into());
+    }
+
+    let broker = Broker::of(rc)?;
+    if broker != plan.broker {
+        return Err(format!(
+            "Plan was made for cluster '{}' at {}, but this is cluster '{}' at {}",
+            plan.broker.cluster_name, plan.broker.endpoint, broker.cluster_name, broker.endpoint
+        )
+        .into());
+    }
+
+    let vhost = plan.vhost.as_str();
+    println!("Plan made at {} for vhost '{}'", plan.created_at, vhost);
+    let mut objects = plan.objects;
+    let current = Fingerprints::of(
+        rc,
+        vhost,
+        &rc.list_queues_paged(vhost, None)?,
+        !objects.exchanges.is_empty(),
+        !objects.policies.is_empty(),
+    )?;
+
+    let planned = &plan.fingerprints;
+    objects
+        .queues
+        .retain(|queue| unchanged("queue", &queue.name, &planned.queues, &current.queues));
+    objects
+        .exchanges
+        .retain(|exchange| unchanged("exchange", exchange, &planned.exchanges, &current.exchanges));
+    objects.policies.retain(|policy| {
+        if policy.operator {
+            unchanged(
+                "operator policy",
+                &policy.name,
+                &planned.operator_policies,
+                &current.operator_policies,
+            )
+        } else {
+            unchanged("policy", &policy.name, &planned.policies, &current.policies)
+        }
+    });
+
+    rc.metrics.observe(vhost, "apply", || {
+        rc.limits
+            .check(dry_run, "delete", &objects.queues, objects.exchanges.len())?;
+        if !dry_run && !confirm_changes(rc, "Apply the plan?", yes)? {
+            return Err("Aborted".into());
+        }
+        delete(rc, vhost, dry_run, &objects, plan.concurrency, None)
+    })
+}
+
+fn unchanged(
+    kind: &str,
+    name: &str,
+    planned: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> bool {
+    match (planned.get(name), current.get(name)) {
+        (Some(planned), Some(current)) if planned == current => true,
+        (_, None) => {
+            println!("⏭️  Skipping {} {} - it no longer exists", kind, name);
+            false
+        }
+        _ => {
+            println!("⏭️  Skipping {} {} - it changed since planning", kind, name);
+            false
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::ApplyPolicyOptions;
+use crate::audit::{AuditedObject, audited};
+use crate::client::RmqClient;
+use crate::collector::Queue;
+use crate::print_line;
+use crate::request::request;
+use rabbitmq_http_client::commons::PolicyTarget;
+use rabbitmq_http_client::requests::{PolicyDefinition, PolicyParams};
+use rabbitmq_http_client::responses::Policy;
+use regex::Regex;
+
+/// All policies created by clean_rmq have this prefix, so they can be told apart from the others
+pub const POLICY_PREFIX: &str = "clean_rmq-";
+
+pub fn apply_policy(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    options: &ApplyPolicyOptions,
+    queues: &[Queue],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let definition = policy_definition(options);
+    if definition.is_empty() {
+        return Err(
+            "At least one of --message-ttl, --expires, --max-length, --overflow, --max-age or --max-length-bytes must be specified"
+                .into(),
+        );
+    }
+
+    let queues: Vec<_> = queues.iter().filter(|q| !q.exclusive).collect();
+    if queues.is_empty() {
+        println!("No queues match the filters, the policy is not applied");
+        return Ok(());
+    }
+
+    let name = policy_name(options);
+    let pattern = policy_pattern(options);
+
+    let policies = request!(rc, rc.list_policies_in(vhost))?;
+    let existing = policies.iter().find(|p| p.name == name);
+
+    print_line(
+        dry_run,
+        &format!(
+            "{} policy {} for {} queues",
+            if existing.is_some() {
+                "Updating"
+            } else {
+                "Creating"
+            },
+            name,
+            queues.len()
+        ),
+    );
+    print_diff(existing, &pattern, options.priority, &definition);
+    print_overlapping_policies(&policies, &name, &queues);
+
+    audited(
+        rc,
+        vhost,
+        "apply",
+        AuditedObject::policy(&name, false),
+        dry_run,
+        || {
+            Ok(request!(
+                rc,
+                rc.declare_policy(&PolicyParams {
+                    vhost,
+                    name: &name,
+                    pattern: &pattern,
+                    apply_to: PolicyTarget::Queues,
+                    priority: options.priority,
+                    definition: definition.clone(),
+                })
+            )?)
+        },
+    )
+}
+
+/// Removes the policy `apply_policy` created for the same filters, or the one with the given name
+pub fn remove_policies(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    options: &ApplyPolicyOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = policy_name(options);
+    let policies: Vec<_> = request!(rc, rc.list_policies_in(vhost))?
+        .into_iter()
+        .filter(|p| p.name == name)
+        .collect();
+
+    for policy in &policies {
+        print_line(dry_run, &format!("Removing policy {}", policy.name));
+        let object = AuditedObject::policy(&policy.name, false);
+        audited(rc, vhost, "delete", object, dry_run, || {
+            Ok(request!(rc, rc.delete_policy(vhost, &policy.name, true))?)
+        })?;
+    }
+
+    println!("Removed {} policies", policies.len());
+
+    Ok(())
+}
+
+fn policy_name(options: &ApplyPolicyOptions) -> String {
+    match &options.name {
+        Some(name) => format!("{}{}", POLICY_PREFIX, name),
+        None => {
+            let filters = std::iter::once(&options.queue_filter)
+                .chain(&options.exclude_queue_filter)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("{}{:016x}", POLICY_PREFIX, fnv1a(filters.as_bytes()))
+        }
+    }
+}
+
+/// Translates the queue filters into a policy pattern, so queues created later are covered too. Like ours,
+/// patterns of the broker match anywhere in the name, exclusions become a negative lookahead
+fn policy_pattern(options: &ApplyPolicyOptions) -> String {
+    if options.exclude_queue_filter.is_empty() {
+        return options.queue_filter.clone();
+    }
+    let exclusions = options
+        .exclude_queue_filter
+        .iter()
+        .map(|filter| format!(".*(?:{})", filter))
+        .collect::<Vec<_>>()
+        .join("|");
+    format!("^(?!{}).*(?:{})", exclusions, options.queue_filter)
+}
+
+/// Hash that is stable between runs and versions, so the same filters always give the same policy name
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn policy_definition(options: &ApplyPolicyOptions) -> PolicyDefinition {
+    let mut definition = PolicyDefinition::new();
+    if let Some(ttl) = options.message_ttl {
+        definition.insert("message-ttl".to_string(), (ttl.as_millis() as u64).into());
+    }
+    if let Some(expires) = options.expires {
+        definition.insert("expires".to_string(), (expires.as_millis() as u64).into());
+    }
+    if let Some(max_length) = options.max_length {
+        definition.insert("max-length".to_string(), max_length.into());
+    }
+    if let Some(overflow) = &options.overflow {
+        definition.insert("overflow".to_string(), overflow.clone().into());
+    }
+    if let Some(max_age) = options.max_age {
+        definition.insert(
+            "max-age".to_string(),
+            format!("{}s", max_age.as_secs()).into(),
+        );
+    }
+    if let Some(max_length_bytes) = options.max_length_bytes {
+        definition.insert("max-length-bytes".to_string(), max_length_bytes.into());
+    }
+    definition
+}
+
+fn print_diff(
+    existing: Option<&Policy>,
+    pattern: &str,
+    priority: i32,
+    definition: &PolicyDefinition,
+) {
+    let Some(existing) = existing else {
+        println!("  pattern: {}", pattern);
+        println!("  priority: {}", priority);
+        for (key, value) in definition {
+            println!("  + {}: {}", key, value);
+        }
+        return;
+    };
+
+    let mut changed = false;
+    if existing.pattern != pattern {
+        println!("  pattern: {} → {}", existing.pattern, pattern);
+        changed = true;
+    }
+    if existing.priority as i32 != priority {
+        println!("  priority: {} → {}", existing.priority, priority);
+        changed = true;
+    }
+
+    let existing_definition = existing.definition.0.clone().unwrap_or_default();
+    for (key, value) in definition {
+        match existing_definition.get(key) {
+            None => println!("  + {}: {}", key, value),
+            Some(old) if old != value => println!("  ~ {}: {} → {}", key, old, value),
+            Some(_) => continue,
+        }
+        changed = true;
+    }
+    for (key, value) in &existing_definition {
+        if !definition.contains_key(key) {
+            println!("  - {}: {}", key, value);
+            changed = true;
+        }
+    }
+
+    if !changed {
+        println!("  no changes");
+    }
+}
+
+/// Only one policy applies to a queue, the one with the highest priority. Reports other policies
+/// that may win over the applied one
+fn print_overlapping_policies(policies: &[Policy], name: &str, queues: &[&Queue]) {
+    for policy in policies {
+        if policy.name == name || policy.apply_to == PolicyTarget::Exchanges {
+            continue;
+        }
+        let Ok(regex) = Regex::new(&policy.pattern) else {
+            continue;
+        };
+
+        let matching = queues.iter().filter(|q| regex.is_match(&q.name)).count();
+        if matching > 0 {
+            println!(
+                "⚠️  Policy {} with priority {} also matches {} of these queues",
+                policy.name, policy.priority, matching
+            );
+        }
+    }
+}
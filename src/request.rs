@@ -0,0 +1,137 @@
+use crate::client::RmqClient;
+use rabbitmq_http_client::blocking_api::HttpClientError;
+use rabbitmq_http_client::error::Error;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+pub struct RequestSettings {
+    /// Minimum time between the starts of two requests
+    interval: Option<Duration>,
+    pub(crate) retries: u32,
+    /// Start time of the next request allowed by the rate limit, shared by all threads
+    next_request: Mutex<Option<Instant>>,
+}
+
+impl RequestSettings {
+    pub fn new(max_requests_per_second: Option<NonZeroU32>, retries: u32) -> RequestSettings {
+        RequestSettings {
+            interval: max_requests_per_second.map(|rps| Duration::from_secs(1) / rps.get()),
+            retries,
+            next_request: Mutex::new(None),
+        }
+    }
+
+    fn wait_for_turn(&self) {
+        if let Some(start) = self.reserve_turn() {
+            std::thread::sleep(start.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    /// Reserves the next start of a request allowed by the rate limit, if there is one
+    pub(crate) fn reserve_turn(&self) -> Option<Instant> {
+        let interval = self.interval?;
+        let mut next = self.next_request.lock().unwrap_or_else(|e| e.into_inner());
+        let start = next.map_or(Instant::now(), |next| next.max(Instant::now()));
+        *next = Some(start + interval);
+        Some(start)
+    }
+
+    /// Delay before sending a request again after it failed with the error, unless it shouldn't be retried.
+    /// Shared by the blocking and the async client
+    pub(crate) fn retry_delay(
+        &self,
+        attempt: u32,
+        error: &impl TransientError,
+    ) -> Option<Duration> {
+        (attempt < self.retries && error.is_transient()).then(|| backoff(attempt))
+    }
+}
+
+/// Errors after which a request may succeed if it's sent again
+pub trait TransientError {
+    fn is_transient(&self) -> bool;
+}
+
+impl TransientError for HttpClientError {
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::ClientErrorResponse { status_code, .. } => status_code.as_u16() == 429,
+            Error::ServerErrorResponse { .. } => true,
+            Error::RequestError { error, .. } => error.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl TransientError for reqwest::Error {
+    fn is_transient(&self) -> bool {
+        self.is_timeout()
+            || self
+                .status()
+                .is_some_and(|status| status.as_u16() == 429 || status.is_server_error())
+    }
+}
+
+impl RmqClient<'_> {
+    /// Sends a request to the management API respecting the rate limit. Requests that failed with 429, 5xx or a timeout
+    /// are retried with exponential backoff and jitter. Only idempotent requests may be sent this way
+    pub fn request<T, E: TransientError>(&self, f: impl Fn() -> Result<T, E>) -> Result<T, E> {
+        let mut attempt = 0;
+        loop {
+            self.requests.wait_for_turn();
+
+            let result = f();
+            match result
+                .as_ref()
+                .err()
+                .and_then(|e| self.requests.retry_delay(attempt, e))
+            {
+                Some(delay) => {
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    /// Sends a request that must not be repeated, like taking messages from a queue, respecting the rate limit
+    pub fn request_once<T, E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        self.requests.wait_for_turn();
+        f()
+    }
+}
+
+/// Sends a call of the management API client with [`RmqClient::request`], or with [`RmqClient::request_once`] after
+/// `once`. The client returns its large error type as
+/// is, so the lint about it is allowed here instead of at every call
+macro_rules! request {
+    (once $rc:expr, $call:expr) => {{
+        #[allow(clippy::result_large_err)]
+        let result = $rc.request_once(|| $call);
+        result
+    }};
+    ($rc:expr, $call:expr) => {{
+        #[allow(clippy::result_large_err)]
+        let result = $rc.request(|| $call);
+        result
+    }};
+}
+pub(crate) use request;
+
+/// Random delay up to an exponentially growing limit ("full jitter"), so clients that failed together
+/// don't retry together
+fn backoff(attempt: u32) -> Duration {
+    let limit = FIRST_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+    let random = RandomState::new().build_hasher().finish();
+    limit.mul_f64(random as f64 / u64::MAX as f64)
+}
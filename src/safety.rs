@@ -0,0 +1,169 @@
+use crate::Action;
+use crate::client::RmqClient;
+use crate::collector::{Queue, get_regex_vec};
+use crate::management::ManagementApi;
+use crate::request::request;
+use regex::Regex;
+use std::io::Write;
+
+/// Limits on the number of objects a single action may change, protecting from too broad filters
+#[derive(Default)]
+pub struct Limits {
+    pub max_queues: Option<u64>,
+    pub max_exchanges: Option<u64>,
+    pub max_messages: Option<u64>,
+    pub force: bool,
+}
+
+impl Limits {
+    /// Checks the collected objects before anything is changed. In dry run mode exceeded limits are only reported
+    pub fn check(
+        &self,
+        dry_run: bool,
+        action: &str,
+        queues: &[Queue],
+        num_exchanges: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let queues: Vec<_> = queues.iter().filter(|q| !q.exclusive).collect();
+        let num_messages: u64 = queues.iter().map(|q| q.messages).sum();
+
+        let exceeded: Vec<_> = [
+            (
+                "queues",
+                "--max-queues",
+                queues.len() as u64,
+                self.max_queues,
+            ),
+            (
+                "exchanges",
+                "--max-exchanges",
+                num_exchanges as u64,
+                self.max_exchanges,
+            ),
+            (
+                "messages",
+                "--max-messages",
+                num_messages,
+                self.max_messages,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(what, flag, count, limit)| {
+            limit
+                .filter(|limit| count > *limit)
+                .map(|limit| format!("{} {} ({} {})", count, what, flag, limit))
+        })
+        .collect();
+
+        if exceeded.is_empty() {
+            return Ok(());
+        }
+
+        let exchanges = if num_exchanges > 0 {
+            format!(" and {} exchanges", num_exchanges)
+        } else {
+            String::new()
+        };
+        let message = format!(
+            "Refusing to {} {} queues with {} messages{}, limits exceeded: {}",
+            action,
+            queues.len(),
+            num_messages,
+            exchanges,
+            exceeded.join(", ")
+        );
+        if self.force {
+            println!("⚠️  {}. Going on because of --force", message);
+            Ok(())
+        } else if dry_run {
+            println!("⚠️  {}. Use --force to override", message);
+            Ok(())
+        } else {
+            Err(format!("{}. Use --force to override", message).into())
+        }
+    }
+}
+
+/// Names no deliberate filter targets. A filter matching all of them selects every queue, like `.+`, `^` or `q*`.
+/// Queue names are never empty, so an empty name isn't a probe: the default `.+` doesn't match it
+const PROBE_NAMES: [&str; 2] = ["clean_rmq-probe", "Z9 ~"];
+
+/// Cluster that destructive actions need typed confirmation of the cluster name for
+pub struct Protection {
+    pub cluster_name: String,
+    /// Cluster name given with --confirm-cluster instead of typing it
+    pub confirmation: Option<String>,
+}
+
+impl Protection {
+    /// Returns the protection of the cluster if the profile is protected or the cluster name reported by the broker
+    /// matches one of the protected cluster filters. Brokers without protection settings aren't asked for their name
+    pub fn detect(
+        rc: &RmqClient,
+        protected_profile: bool,
+        protected_clusters: &[Regex],
+        confirmation: Option<String>,
+    ) -> Result<Option<Protection>, Box<dyn std::error::Error>> {
+        if !protected_profile && protected_clusters.is_empty() {
+            return Ok(None);
+        }
+
+        let cluster_name = request!(rc, rc.overview())?.cluster_name;
+        let protected =
+            protected_profile || protected_clusters.iter().any(|f| f.is_match(&cluster_name));
+        Ok(protected.then_some(Protection {
+            cluster_name,
+            confirmation,
+        }))
+    }
+
+    /// Refuses actions selecting all queues, so a forgotten filter doesn't hit a whole protected cluster. Besides filters
+    /// that match anything, this refuses filters matching every queue of a virtual host with more than one queue
+    pub fn check_queue_filter(
+        &self,
+        api: &impl ManagementApi,
+        vhost: &str,
+        action: &Action,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((filter, exclude_filters)) = action.queue_filter() else {
+            return Ok(());
+        };
+        let include = Regex::new(filter)?;
+        let selects_all = PROBE_NAMES.iter().all(|name| include.is_match(name)) || {
+            let excludes = get_regex_vec(exclude_filters)?;
+            let queues = api.queues(vhost, None)?;
+            queues.len() > 1
+                && queues.iter().all(|q| {
+                    include.is_match(&q.name) && excludes.iter().all(|e| !e.is_match(&q.name))
+                })
+        };
+
+        if selects_all {
+            Err(format!(
+                "Queue filter '{}' selects all queues of protected cluster '{}', specify a narrower filter with -f",
+                filter, self.cluster_name
+            )
+            .into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Asks to type the cluster name. --yes doesn't answer this question
+    pub fn confirm(&self, question: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let answer = match &self.confirmation {
+            Some(confirmation) => confirmation.clone(),
+            None => {
+                print!(
+                    "{} Type the name of protected cluster '{}' to confirm: ",
+                    question, self.cluster_name
+                );
+                std::io::stdout().flush()?;
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                answer
+            }
+        };
+        Ok(answer.trim() == self.cluster_name)
+    }
+}
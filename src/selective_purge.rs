@@ -0,0 +1,398 @@
+use crate::audit::{AuditedObject, audited};
+use crate::client::RmqClient;
+use crate::collector::Queue;
+use crate::metrics::Counter;
+use crate::print_line;
+use crate::request::request;
+use rabbitmq_http_client::responses::GetMessage;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Part of a message a predicate is applied to
+enum MessageField {
+    RoutingKey,
+    Payload,
+    Header(String),
+    JsonPath(Vec<String>),
+}
+
+/// Predicate of form `<field>=<regex>` where field is one of
+/// `routing_key`, `payload`, `header.<name>` or `json.<path.to.value>`
+pub struct MessagePredicate {
+    field: MessageField,
+    regex: Regex,
+}
+
+impl MessagePredicate {
+    pub fn parse(expression: &str) -> Result<MessagePredicate, Box<dyn std::error::Error>> {
+        let (field, regex) = expression
+            .split_once('=')
+            .ok_or_else(|| format!("Predicate '{}' must have form <field>=<regex>", expression))?;
+
+        let field = match field {
+            "routing_key" => MessageField::RoutingKey,
+            "payload" => MessageField::Payload,
+            _ => {
+                if let Some(header) = field.strip_prefix("header.") {
+                    MessageField::Header(header.to_string())
+                } else if let Some(path) = field.strip_prefix("json.") {
+                    MessageField::JsonPath(path.split('.').map(String::from).collect())
+                } else {
+                    return Err(format!(
+                        "Unknown field '{}' in predicate '{}'. Use routing_key, payload, header.<name> or json.<path>",
+                        field, expression
+                    )
+                    .into());
+                }
+            }
+        };
+
+        Ok(MessagePredicate {
+            field,
+            regex: Regex::new(regex)?,
+        })
+    }
+
+    pub fn is_match(&self, message: &GetMessage) -> bool {
+        match &self.field {
+            MessageField::RoutingKey => self.regex.is_match(&message.routing_key),
+            MessageField::Payload => self.regex.is_match(&message.payload),
+            MessageField::Header(name) => message
+                .properties
+                .0
+                .get("headers")
+                .and_then(|headers| headers.get(name))
+                .is_some_and(|value| self.is_value_match(value)),
+            MessageField::JsonPath(path) => serde_json::from_str::<Value>(&message.payload)
+                .ok()
+                .and_then(|payload| find_json_value(payload, path))
+                .is_some_and(|value| self.is_value_match(&value)),
+        }
+    }
+
+    fn is_value_match(&self, value: &Value) -> bool {
+        match value {
+            Value::String(s) => self.regex.is_match(s),
+            other => self.regex.is_match(&other.to_string()),
+        }
+    }
+}
+
+fn find_json_value(payload: Value, path: &[String]) -> Option<Value> {
+    path.iter().try_fold(payload, |value, segment| match value {
+        Value::Array(mut items) => {
+            let index: usize = segment.parse().ok()?;
+            (index < items.len()).then(|| items.swap_remove(index))
+        }
+        Value::Object(mut map) => map.remove(segment),
+        _ => None,
+    })
+}
+
+/// Drains the messages that are in each queue at the moment of the call, drops the ones matching all predicates
+/// and republishes the rest to the tail of the same queue via the default exchange. Retained messages end up behind
+/// the ones published meanwhile and, if the purge stops early, behind the ones that weren't processed yet.
+/// A dry run inspects only the first batch, since messages requeued by the HTTP API return to the head of the queue
+pub fn purge_where(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    queues: &Vec<Queue>,
+    predicates: &[MessagePredicate],
+    batch_size: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut num_purged_queues = 0;
+    let mut num_exclusive = 0;
+
+    for queue in queues {
+        if queue.exclusive {
+            println!("🚫  Skipping exclusive queue {}", queue.name);
+            num_exclusive += 1;
+            continue;
+        }
+
+        let mut counts = (0, 0);
+        if dry_run {
+            counts = count_matching(rc, vhost, queue, predicates, batch_size)?;
+            if counts.0 + counts.1 < queue.messages {
+                println!(
+                    "⚠️  Only the first {} of {} messages in queue {} were inspected",
+                    counts.0 + counts.1,
+                    queue.messages,
+                    queue.name
+                );
+            }
+        }
+        let object = AuditedObject::queue(&queue.name, queue.messages);
+        audited(rc, vhost, "purge", object, dry_run, || {
+            counts = drain_queue(rc, vhost, queue, predicates, batch_size)?;
+            Ok(())
+        })?;
+        let (dropped, retained) = counts;
+        if !dry_run {
+            rc.metrics.add(Counter::MessagesPurged, dropped);
+        }
+
+        print_line(
+            dry_run,
+            &format!(
+                "Purging queue {} - dropped {}, retained {}",
+                queue.name, dropped, retained
+            ),
+        );
+        num_purged_queues += 1;
+    }
+
+    println!(
+        "Purged {} queues, skipped {} exclusive queues",
+        num_purged_queues, num_exclusive
+    );
+
+    Ok(())
+}
+
+fn is_match(predicates: &[MessagePredicate], message: &GetMessage) -> bool {
+    predicates.iter().all(|p| p.is_match(message))
+}
+
+fn count_matching(
+    rc: &RmqClient,
+    vhost: &str,
+    queue: &Queue,
+    predicates: &[MessagePredicate],
+    batch_size: u32,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let count = batch_size.min(to_count(queue.messages));
+    let messages = request!(
+        rc,
+        rc.get_messages(vhost, &queue.name, count, "ack_requeue_true")
+    )?;
+    let dropped = messages.iter().filter(|m| is_match(predicates, m)).count() as u64;
+    Ok((dropped, messages.len() as u64 - dropped))
+}
+
+fn drain_queue(
+    rc: &RmqClient,
+    vhost: &str,
+    queue: &Queue,
+    predicates: &[MessagePredicate],
+    batch_size: u32,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let mut dropped = 0;
+    let mut retained = 0;
+    let mut remaining = queue.messages;
+
+    while remaining > 0 {
+        let count = batch_size.min(to_count(remaining));
+        let messages =
+            request!(once rc, rc.get_messages(vhost, &queue.name, count, "ack_requeue_false"))?;
+        if messages.is_empty() {
+            break;
+        }
+        remaining = remaining.saturating_sub(messages.len() as u64);
+
+        // Taken messages exist only here, so every retained one is republished before an error is returned
+        let mut failure = None;
+        for message in messages {
+            if is_match(predicates, &message) {
+                dropped += 1;
+                continue;
+            }
+
+            match republish(rc, vhost, &queue.name, message) {
+                Ok(()) => retained += 1,
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = failure {
+            return Err(e);
+        }
+    }
+
+    Ok((dropped, retained))
+}
+
+/// Puts a message taken from a queue back to its tail. The message goes through the default exchange,
+/// so its original exchange and routing key are kept in headers
+fn republish(
+    rc: &RmqClient,
+    vhost: &str,
+    queue: &str,
+    message: GetMessage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut properties = message.properties.0;
+    if let Value::Object(headers) = properties
+        .entry("headers")
+        .or_insert_with(|| Value::Object(Map::new()))
+    {
+        headers
+            .entry("x-original-exchange")
+            .or_insert(message.exchange.into());
+        headers
+            .entry("x-original-routing-key")
+            .or_insert(message.routing_key.into());
+    }
+
+    let routed = rc.request_once(|| {
+        rc.republish(
+            vhost,
+            queue,
+            &message.payload,
+            &message.payload_encoding,
+            &properties,
+        )
+    })?;
+    if !routed {
+        return Err(format!(
+            "Message republished to queue {} wasn't routed and is lost",
+            queue
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Takes messages from the head of each queue while they are older than `max_age` and drops them.
+/// Stops at the first message that is younger or doesn't have a timestamp. A dry run inspects only the first batch
+pub fn purge_older_than(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    queues: &Vec<Queue>,
+    max_age: Duration,
+    timestamp_header: Option<&str>,
+    batch_size: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .saturating_sub(max_age)
+        .as_secs();
+    let mut num_purged_queues = 0;
+    let mut num_exclusive = 0;
+
+    for queue in queues {
+        if queue.exclusive {
+            println!("🚫  Skipping exclusive queue {}", queue.name);
+            num_exclusive += 1;
+            continue;
+        }
+
+        let mut dropped = 0;
+        if dry_run {
+            let count = batch_size.min(to_count(queue.messages));
+            let messages = request!(
+                rc,
+                rc.get_messages(vhost, &queue.name, count, "ack_requeue_true")
+            )?;
+            dropped = count_leading_older(&messages, cutoff, timestamp_header);
+            if dropped == messages.len() as u64 && dropped < queue.messages {
+                println!(
+                    "⚠️  Only the first {} of {} messages in queue {} were inspected",
+                    dropped, queue.messages, queue.name
+                );
+            }
+        }
+        let object = AuditedObject::queue(&queue.name, queue.messages);
+        audited(rc, vhost, "purge", object, dry_run, || {
+            dropped = drain_older(rc, vhost, queue, cutoff, timestamp_header, batch_size)?;
+            Ok(())
+        })?;
+        if !dry_run {
+            rc.metrics.add(Counter::MessagesPurged, dropped);
+        }
+
+        print_line(
+            dry_run,
+            &format!(
+                "Purging queue {} - dropped {}, retained {}",
+                queue.name,
+                dropped,
+                queue.messages.saturating_sub(dropped)
+            ),
+        );
+        num_purged_queues += 1;
+    }
+
+    println!(
+        "Purged {} queues, skipped {} exclusive queues",
+        num_purged_queues, num_exclusive
+    );
+
+    Ok(())
+}
+
+fn drain_older(
+    rc: &RmqClient,
+    vhost: &str,
+    queue: &Queue,
+    cutoff: u64,
+    timestamp_header: Option<&str>,
+    batch_size: u32,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut dropped = 0;
+
+    while dropped < queue.messages {
+        let count = batch_size.min(to_count(queue.messages - dropped));
+        let preview = request!(
+            rc,
+            rc.get_messages(vhost, &queue.name, count, "ack_requeue_true")
+        )?;
+        let older = count_leading_older(&preview, cutoff, timestamp_header);
+        if older > 0 {
+            // The head may have changed since the preview, e.g. because of a consumer, so taken messages are checked
+            // again. Younger ones and everything after them are put back
+            let taken = request!(once rc, rc.get_messages(vhost, &queue.name, to_count(older), "ack_requeue_false"))?;
+            let taken_older = count_leading_older(&taken, cutoff, timestamp_header);
+            dropped += taken_older;
+
+            let mut failure = None;
+            for message in taken.into_iter().skip(taken_older as usize) {
+                if let Err(e) = republish(rc, vhost, &queue.name, message) {
+                    failure.get_or_insert(e);
+                }
+            }
+            if let Some(e) = failure {
+                return Err(e);
+            }
+            if taken_older < older {
+                println!(
+                    "⚠️  Queue {} changed while purging, younger messages were moved to its end",
+                    queue.name
+                );
+                break;
+            }
+        }
+        if older < preview.len() as u64 || preview.is_empty() {
+            break;
+        }
+    }
+
+    Ok(dropped)
+}
+
+fn count_leading_older(
+    messages: &[GetMessage],
+    cutoff: u64,
+    timestamp_header: Option<&str>,
+) -> u64 {
+    messages
+        .iter()
+        .take_while(|m| message_timestamp(m, timestamp_header).is_some_and(|t| t < cutoff))
+        .count() as u64
+}
+
+fn message_timestamp(message: &GetMessage, timestamp_header: Option<&str>) -> Option<u64> {
+    let properties = &message.properties.0;
+    match timestamp_header {
+        Some(name) => properties.get("headers").and_then(|h| h.get(name)),
+        None => properties.get("timestamp"),
+    }
+    .and_then(Value::as_u64)
+}
+
+fn to_count(messages: u64) -> u32 {
+    u32::try_from(messages).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,217 @@
+use crate::args::{deserialize_age, parse_age};
+use crate::client::RmqClient;
+use crate::collector::{QueueName, collect_queues_by_name};
+use crate::{Action, WatchOptions, execute};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// A rule from the rules file, e.g.
+/// ```toml
+/// # rules run without confirmation, so closing connections, e.g. by force-consumers, has to be allowed explicitly
+/// allow-closing-connections = false
+///
+/// [[rule]]
+/// name = "old error messages"
+/// vhost = "dev"
+/// interval = "10m"
+/// min-messages = 100
+/// idle-for = "1h"
+///
+/// [rule.purge]
+/// queue-filter = ".*_error"
+/// older-than = "1d"
+/// ```
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Rule {
+    name: String,
+    vhost: Option<String>,
+    #[serde(deserialize_with = "deserialize_interval")]
+    interval: Duration,
+    /// Queues with fewer messages are skipped
+    #[serde(default)]
+    min_messages: u64,
+    /// Queues that had consumers or whose number of messages changed within this time are skipped
+    #[serde(default, deserialize_with = "deserialize_age")]
+    idle_for: Option<Duration>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RulesFile {
+    #[serde(default)]
+    allow_closing_connections: bool,
+    #[serde(rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// Last observed state of a queue, used to find out for how long it has been idle
+struct QueueActivity {
+    messages: u64,
+    consumers: u16,
+    since: Instant,
+}
+
+type ActivityLog = HashMap<(String, QueueName), QueueActivity>;
+
+pub fn watch(
+    rc: &RmqClient,
+    default_vhost: &str,
+    dry_run: bool,
+    options: &WatchOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let RulesFile {
+        allow_closing_connections,
+        rules,
+    } = toml::from_str(&std::fs::read_to_string(&options.rules)?)?;
+    if rules.is_empty() {
+        return Err("Rules file doesn't contain any rules".into());
+    }
+    if !allow_closing_connections
+        && let Some(rule) = rules.iter().find(|r| r.action.closes_connections())
+    {
+        return Err(format!(
+            "Rule '{}' closes connections, which happens without confirmation in watch mode. Set allow-closing-connections = true in the rules file to allow it",
+            rule.name
+        )
+        .into());
+    }
+
+    let stop = stop_flag();
+    let mut next_runs = vec![Instant::now(); rules.len()];
+    let mut activity = ActivityLog::new();
+    let mut cycle = 0;
+
+    // rules run one after another in a single thread, so cycles never overlap
+    while !stop.load(Ordering::SeqCst) {
+        cycle += 1;
+        let started = Instant::now();
+        log(&format!("Cycle {} started", cycle));
+
+        for (rule, next_run) in rules.iter().zip(next_runs.iter_mut()) {
+            if *next_run > started {
+                continue;
+            }
+            *next_run = started + rule.interval;
+
+            let vhost = rule.vhost.as_deref().unwrap_or(default_vhost);
+            log(&format!(
+                "Running rule '{}' on vhost '{}'",
+                rule.name, vhost
+            ));
+            if let Err(e) = rc.metrics.observe(vhost, &rule.name, || {
+                run_rule(rc, vhost, dry_run, rule, &mut activity)
+            }) {
+                log(&format!("Rule '{}' failed: {}", rule.name, e));
+            }
+        }
+
+        log(&format!(
+            "Cycle {} finished in {:.1?}",
+            cycle,
+            started.elapsed()
+        ));
+        if let Err(e) = rc.metrics.flush() {
+            log(&format!("Metrics can't be written: {}", e));
+        }
+        if options.max_cycles != 0 && cycle >= options.max_cycles {
+            break;
+        }
+
+        let next_run = *next_runs.iter().min().expect("There is at least one rule");
+        while !stop.load(Ordering::SeqCst) && Instant::now() < next_run {
+            std::thread::sleep(
+                next_run
+                    .duration_since(Instant::now())
+                    .min(Duration::from_secs(1)),
+            );
+        }
+    }
+
+    log("Stopped");
+    Ok(())
+}
+
+fn run_rule(
+    rc: &RmqClient,
+    vhost: &str,
+    dry_run: bool,
+    rule: &Rule,
+    activity: &mut ActivityLog,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut action = rule.action.clone();
+
+    // thresholds are applied by excluding the queues that don't pass them
+    if rule.min_messages > 0 || rule.idle_for.is_some() {
+        let now = Instant::now();
+        let excluded: HashSet<_> = collect_queues_by_name(rc, vhost, "", &[])?
+            .into_iter()
+            .filter(|queue| {
+                let last = activity
+                    .entry((vhost.to_string(), queue.name.clone()))
+                    .or_insert(QueueActivity {
+                        messages: queue.messages,
+                        consumers: queue.consumer_count,
+                        since: now,
+                    });
+                if last.messages != queue.messages || last.consumers != queue.consumer_count {
+                    *last = QueueActivity {
+                        messages: queue.messages,
+                        consumers: queue.consumer_count,
+                        since: now,
+                    };
+                }
+
+                let idle = rule.idle_for.is_none_or(|idle_for| {
+                    queue.consumer_count == 0 && now.duration_since(last.since) >= idle_for
+                });
+                queue.messages < rule.min_messages || !idle
+            })
+            .map(|queue| queue.name)
+            .collect();
+
+        *action.exclude_queues_mut().ok_or(
+            "min-messages and idle-for work only with purge, delete, peek and plan actions",
+        )? = excluded;
+    }
+
+    // nobody can answer confirmation questions in a long-running process
+    execute(rc, vhost, dry_run, true, action)
+}
+
+/// A zero interval would run the rule again right away, flooding the broker
+fn deserialize_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let interval = parse_age(&String::deserialize(deserializer)?).map_err(D::Error::custom)?;
+    if interval.is_zero() {
+        return Err(D::Error::custom("interval must be longer than 0s"));
+    }
+    Ok(interval)
+}
+
+/// Flag raised by SIGTERM or Ctrl+C. The handler can be installed only once per process
+fn stop_flag() -> Arc<AtomicBool> {
+    static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    STOP.get_or_init(|| {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handler_stop = stop.clone();
+        if let Err(e) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst)) {
+            log(&format!("Termination signals can't be handled: {}", e));
+        }
+        stop
+    })
+    .clone()
+}
+
+fn log(message: &str) {
+    println!(
+        "[{}] {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        message
+    );
+}
@@ -0,0 +1,883 @@
+use regex::RegexBuilder;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_EXCHANGES: [(&str, &str); 7] = [
+    ("", "direct"),
+    ("amq.direct", "direct"),
+    ("amq.fanout", "fanout"),
+    ("amq.topic", "topic"),
+    ("amq.headers", "headers"),
+    ("amq.match", "headers"),
+    ("amq.rabbitmq.trace", "topic"),
+];
+
+/// Nodes with a member of every quorum queue. The first one is the leader until the test changes it
+const QUORUM_MEMBERS: [&str; 3] = ["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"];
+
+/// Stand-in for the management API of a broker, covering the part clean_rmq and the tests use. Classic queues
+/// live on the first node, quorum queues have members on all three
+/// State is kept in memory, messages are routed on publish, so nothing has to be waited for
+pub struct FakeServer {
+    endpoint: String,
+    state: Arc<Mutex<State>>,
+}
+
+/// Makes matching requests fail or slow down, for tests of retries and error handling
+#[derive(Clone)]
+pub struct Fault {
+    method: &'static str,
+    /// Part of the decoded path, e.g. the name of the test's vhost
+    path: String,
+    status: Option<u16>,
+    delay: Option<Duration>,
+    times: usize,
+}
+
+#[derive(Default)]
+struct State {
+    vhosts: BTreeMap<String, Vhost>,
+    faults: Vec<Fault>,
+    next_port: u32,
+}
+
+#[derive(Default)]
+struct Vhost {
+    queues: BTreeMap<String, Queue>,
+    exchanges: BTreeMap<String, Exchange>,
+    bindings: Vec<Binding>,
+    policies: BTreeMap<String, Value>,
+    operator_policies: BTreeMap<String, Value>,
+    connections: BTreeMap<String, Connection>,
+}
+
+/// Client connection with a single channel, which consumes from the listed queues
+struct Connection {
+    client_name: String,
+    port: u32,
+    /// Unix time in milliseconds
+    connected_at: u64,
+    consumed_queues: Vec<String>,
+}
+
+struct Queue {
+    queue_type: String,
+    durable: bool,
+    auto_delete: bool,
+    arguments: Value,
+    messages: VecDeque<Message>,
+    leader: Option<String>,
+    online: Vec<String>,
+}
+
+struct Exchange {
+    exchange_type: String,
+    durable: bool,
+    auto_delete: bool,
+    arguments: Value,
+}
+
+struct Binding {
+    source: String,
+    destination: String,
+    destination_type: &'static str,
+    routing_key: String,
+}
+
+#[derive(Clone)]
+struct Message {
+    exchange: String,
+    routing_key: String,
+    payload: String,
+    payload_encoding: String,
+    properties: Value,
+    redelivered: bool,
+}
+
+struct Request {
+    method: String,
+    path: Vec<String>,
+    query: BTreeMap<String, String>,
+    body: Value,
+}
+
+struct Response {
+    status: u16,
+    body: Option<Value>,
+}
+
+impl Fault {
+    pub fn status(method: &'static str, path: &str, status: u16, times: usize) -> Fault {
+        Fault {
+            method,
+            path: path.to_string(),
+            status: Some(status),
+            delay: None,
+            times,
+        }
+    }
+
+    pub fn delay(method: &'static str, path: &str, delay: Duration, times: usize) -> Fault {
+        Fault {
+            method,
+            path: path.to_string(),
+            status: None,
+            delay: Some(delay),
+            times,
+        }
+    }
+}
+
+impl FakeServer {
+    /// Listens on a random local port and serves every connection on its own thread
+    pub fn start() -> FakeServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Fake server can't listen");
+        let endpoint = format!(
+            "http://localhost:{}/api",
+            listener.local_addr().unwrap().port()
+        );
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = server_state.clone();
+                std::thread::spawn(move || serve(stream, &state));
+            }
+        });
+
+        FakeServer { endpoint, state }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push(fault);
+    }
+
+    /// Opens a connection as if a client connected `connected_for` ago and started consuming from the given queues.
+    /// A connection without consumers has been idle since it was opened. Returns the name of the connection
+    pub fn open_connection(
+        &self,
+        vhost: &str,
+        client_name: &str,
+        consumed_queues: &[&str],
+        connected_for: Duration,
+    ) -> String {
+        let mut state = self.state.lock().unwrap();
+        state.next_port += 1;
+        let port = 50000 + state.next_port;
+        let name = format!("127.0.0.1:{} -> 127.0.0.1:5672", port);
+        let connection = Connection {
+            client_name: client_name.to_string(),
+            port,
+            connected_at: now_millis().saturating_sub(connected_for.as_millis() as u64),
+            consumed_queues: consumed_queues.iter().map(|q| q.to_string()).collect(),
+        };
+        state
+            .vhosts
+            .get_mut(vhost)
+            .expect("Vhost doesn't exist")
+            .connections
+            .insert(name.clone(), connection);
+        name
+    }
+
+    /// Changes the leader and the online members of a quorum queue, e.g. to simulate nodes that are down
+    pub fn set_quorum_state(
+        &self,
+        vhost: &str,
+        queue: &str,
+        leader: Option<&str>,
+        online: &[&str],
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let queue = state
+            .vhosts
+            .get_mut(vhost)
+            .and_then(|v| v.queues.get_mut(queue))
+            .expect("Queue doesn't exist");
+        queue.leader = leader.map(str::to_string);
+        queue.online = online.iter().map(|node| node.to_string()).collect();
+    }
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) {
+    let mut reader = BufReader::new(&stream);
+    let Some(request) = read_request(&mut reader) else {
+        return;
+    };
+
+    let fault = take_fault(state, &request);
+    if let Some(delay) = fault.as_ref().and_then(|f| f.delay) {
+        std::thread::sleep(delay);
+    }
+    let response = match fault.and_then(|f| f.status) {
+        Some(status) => Response::error(status, "Injected fault"),
+        None => handle(&mut state.lock().unwrap(), &request),
+    };
+
+    let body = response
+        .body
+        .map(|body| body.to_string())
+        .unwrap_or_default();
+    let _ = write!(
+        &stream,
+        "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        body.len(),
+        body
+    );
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Some(Request {
+        method,
+        path: path
+            .trim_start_matches("/api/")
+            .split('/')
+            .map(decode)
+            .collect(),
+        query: query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (decode(k), decode(&v.replace('+', " "))))
+            .collect(),
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    })
+}
+
+fn decode(part: &str) -> String {
+    let bytes = part.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], part.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn take_fault(state: &Mutex<State>, request: &Request) -> Option<Fault> {
+    let mut state = state.lock().unwrap();
+    let path = request.path.join("/");
+    let i = state
+        .faults
+        .iter()
+        .position(|f| f.method == request.method && path.contains(&f.path))?;
+
+    state.faults[i].times -= 1;
+    if state.faults[i].times == 0 {
+        Some(state.faults.remove(i))
+    } else {
+        Some(state.faults[i].clone())
+    }
+}
+
+impl Response {
+    fn ok(body: Value) -> Response {
+        Response {
+            status: 200,
+            body: Some(body),
+        }
+    }
+
+    fn no_content() -> Response {
+        Response {
+            status: 204,
+            body: None,
+        }
+    }
+
+    fn created() -> Response {
+        Response {
+            status: 201,
+            body: None,
+        }
+    }
+
+    fn not_found() -> Response {
+        Response::error(404, "Object Not Found")
+    }
+
+    fn error(status: u16, reason: &str) -> Response {
+        Response {
+            status,
+            body: Some(json!({"error": reason, "reason": reason})),
+        }
+    }
+}
+
+fn handle(state: &mut State, request: &Request) -> Response {
+    let path: Vec<&str> = request.path.iter().map(String::as_str).collect();
+    match (request.method.as_str(), path.as_slice()) {
+        ("GET", ["overview"]) => Response::ok(json!({
+            "cluster_name": "rabbit@fake",
+            "node": "rabbit@fake",
+            "rabbitmq_version": "4.0.0",
+            "product_name": "RabbitMQ",
+            "product_version": "4.0.0"
+        })),
+        ("GET", ["nodes"]) => Response::ok(Value::Array(
+            QUORUM_MEMBERS
+                .iter()
+                .map(|node| {
+                    json!({"name": node, "running": true, "being_drained": false, "uptime": 3_600_000})
+                })
+                .collect(),
+        )),
+        ("GET", ["vhosts"]) => Response::ok(Value::Array(
+            state
+                .vhosts
+                .keys()
+                .map(|name| json!({"name": name, "description": "", "tags": []}))
+                .collect(),
+        )),
+        ("PUT", ["vhosts", name]) => {
+            if state.vhosts.contains_key(*name) {
+                return Response::no_content();
+            }
+            let mut vhost = Vhost::default();
+            for (exchange, exchange_type) in DEFAULT_EXCHANGES {
+                vhost.exchanges.insert(
+                    exchange.to_string(),
+                    Exchange {
+                        exchange_type: exchange_type.to_string(),
+                        durable: true,
+                        auto_delete: false,
+                        arguments: json!({}),
+                    },
+                );
+            }
+            state.vhosts.insert(name.to_string(), vhost);
+            Response::created()
+        }
+        ("DELETE", ["vhosts", name]) => match state.vhosts.remove(*name) {
+            Some(_) => Response::no_content(),
+            None => Response::not_found(),
+        },
+        ("DELETE", ["connections", name]) => {
+            // closing a connection cancels its consumers too
+            match state
+                .vhosts
+                .values_mut()
+                .find_map(|v| v.connections.remove(*name))
+            {
+                Some(_) => Response::no_content(),
+                None => Response::not_found(),
+            }
+        }
+        (_, [kind, vhost, rest @ ..]) => match state.vhosts.get_mut(*vhost) {
+            Some(v) => handle_in_vhost(v, vhost, kind, rest, request),
+            None => Response::not_found(),
+        },
+        _ => Response::not_found(),
+    }
+}
+
+fn handle_in_vhost(
+    v: &mut Vhost,
+    vhost: &str,
+    kind: &str,
+    rest: &[&str],
+    request: &Request,
+) -> Response {
+    match (request.method.as_str(), kind, rest) {
+        ("GET", "queues", []) => {
+            let queues = v
+                .queues
+                .iter()
+                .map(|(name, q)| queue_json(vhost, name, q, consumer_count(v, name)))
+                .collect();
+            list(queues, request)
+        }
+        ("GET", "queues", [name]) => match v.queues.get(*name) {
+            Some(q) => Response::ok(queue_json(vhost, name, q, consumer_count(v, name))),
+            None => Response::not_found(),
+        },
+        ("PUT", "queues", [name]) => {
+            if v.queues.contains_key(*name) {
+                return Response::no_content();
+            }
+            let arguments = request.body["arguments"].clone();
+            let queue_type = arguments["x-queue-type"].as_str().unwrap_or("classic");
+            let members: &[&str] = if queue_type == "quorum" {
+                &QUORUM_MEMBERS
+            } else {
+                &[]
+            };
+            v.queues.insert(
+                name.to_string(),
+                Queue {
+                    queue_type: queue_type.to_string(),
+                    durable: request.body["durable"].as_bool().unwrap_or(true),
+                    auto_delete: request.body["auto_delete"].as_bool().unwrap_or(false),
+                    arguments: if arguments.is_null() {
+                        json!({})
+                    } else {
+                        arguments
+                    },
+                    messages: VecDeque::new(),
+                    leader: members.first().map(|node| node.to_string()),
+                    online: members.iter().map(|node| node.to_string()).collect(),
+                },
+            );
+            Response::created()
+        }
+        ("DELETE", "queues", [name]) => match v.queues.remove(*name) {
+            Some(_) => {
+                v.bindings
+                    .retain(|b| b.destination_type != "queue" || b.destination != *name);
+                Response::no_content()
+            }
+            None => Response::not_found(),
+        },
+        ("DELETE", "queues", [name, "contents"]) => match v.queues.get_mut(*name) {
+            Some(q) => {
+                q.messages.clear();
+                Response::no_content()
+            }
+            None => Response::not_found(),
+        },
+        ("POST", "queues", [name, "get"]) => {
+            let Some(q) = v.queues.get_mut(*name) else {
+                return Response::not_found();
+            };
+            // the broker refuses basic.get on streams
+            if q.queue_type == "stream" {
+                return Response::error(400, "Streams can't be read with a get");
+            }
+            let count = request.body["count"].as_u64().unwrap_or(1) as usize;
+            let requeue = request.body["ackmode"].as_str() != Some("ack_requeue_false");
+            let taken: Vec<Message> = if requeue {
+                let taken = q.messages.iter().take(count).cloned().collect();
+                for message in q.messages.iter_mut().take(count) {
+                    message.redelivered = true;
+                }
+                taken
+            } else {
+                q.messages.drain(..count.min(q.messages.len())).collect()
+            };
+            let remaining = q
+                .messages
+                .len()
+                .saturating_sub(if requeue { taken.len() } else { 0 });
+            Response::ok(Value::Array(
+                taken
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, m)| {
+                        json!({
+                            "payload_bytes": m.payload.len(),
+                            "redelivered": m.redelivered,
+                            "exchange": m.exchange,
+                            "routing_key": m.routing_key,
+                            "message_count": remaining.saturating_sub(i),
+                            "properties": m.properties,
+                            "payload": m.payload,
+                            "payload_encoding": m.payload_encoding
+                        })
+                    })
+                    .collect(),
+            ))
+        }
+        ("GET", "exchanges", []) => {
+            let exchanges = v
+                .exchanges
+                .iter()
+                .map(|(name, x)| {
+                    json!({
+                        "name": name,
+                        "vhost": vhost,
+                        "type": x.exchange_type,
+                        "durable": x.durable,
+                        "auto_delete": x.auto_delete,
+                        "internal": false,
+                        "arguments": x.arguments
+                    })
+                })
+                .collect();
+            list(exchanges, request)
+        }
+        ("PUT", "exchanges", [name]) => {
+            if v.exchanges.contains_key(*name) {
+                return Response::no_content();
+            }
+            v.exchanges.insert(
+                name.to_string(),
+                Exchange {
+                    exchange_type: request.body["type"]
+                        .as_str()
+                        .unwrap_or("direct")
+                        .to_string(),
+                    durable: request.body["durable"].as_bool().unwrap_or(true),
+                    auto_delete: request.body["auto_delete"].as_bool().unwrap_or(false),
+                    arguments: json!({}),
+                },
+            );
+            Response::created()
+        }
+        ("DELETE", "exchanges", [name]) => match v.exchanges.remove(*name) {
+            Some(_) => {
+                v.bindings.retain(|b| {
+                    b.source != *name
+                        && (b.destination_type != "exchange" || b.destination != *name)
+                });
+                Response::no_content()
+            }
+            None => Response::not_found(),
+        },
+        ("POST", "exchanges", [name, "publish"]) => {
+            if !v.exchanges.contains_key(*name) {
+                return Response::not_found();
+            }
+            let message = Message {
+                exchange: name.to_string(),
+                routing_key: request.body["routing_key"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+                payload: request.body["payload"].as_str().unwrap_or("").to_string(),
+                payload_encoding: request.body["payload_encoding"]
+                    .as_str()
+                    .unwrap_or("string")
+                    .to_string(),
+                properties: request.body["properties"].clone(),
+                redelivered: false,
+            };
+            let routed = route(v, &message);
+            Response::ok(json!({"routed": routed}))
+        }
+        ("GET", "bindings", []) => Response::ok(Value::Array(
+            v.bindings
+                .iter()
+                .map(|b| {
+                    json!({
+                        "source": b.source,
+                        "vhost": vhost,
+                        "destination": b.destination,
+                        "destination_type": b.destination_type,
+                        "routing_key": b.routing_key,
+                        "arguments": {},
+                        "properties_key": "~"
+                    })
+                })
+                .collect(),
+        )),
+        ("POST", "bindings", ["e", source, kind @ ("q" | "e"), destination]) => {
+            let (destination_type, exists) = if *kind == "q" {
+                ("queue", v.queues.contains_key(*destination))
+            } else {
+                ("exchange", v.exchanges.contains_key(*destination))
+            };
+            if !exists || !v.exchanges.contains_key(*source) {
+                return Response::not_found();
+            }
+            v.bindings.push(Binding {
+                source: source.to_string(),
+                destination: destination.to_string(),
+                destination_type,
+                routing_key: request.body["routing_key"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+            });
+            Response::created()
+        }
+        ("GET", "policies" | "operator-policies", []) => {
+            let policies = policies_mut(v, kind);
+            Response::ok(Value::Array(policies.values().cloned().collect()))
+        }
+        ("PUT", "policies" | "operator-policies", [name]) => {
+            let mut policy = request.body.clone();
+            policy["name"] = json!(name);
+            policy["vhost"] = json!(vhost);
+            policies_mut(v, kind).insert(name.to_string(), policy);
+            Response::created()
+        }
+        ("DELETE", "policies" | "operator-policies", [name]) => {
+            match policies_mut(v, kind).remove(*name) {
+                Some(_) => Response::no_content(),
+                None => Response::not_found(),
+            }
+        }
+        ("GET", "vhosts", ["connections"]) => Response::ok(Value::Array(
+            v.connections
+                .iter()
+                .map(|(name, c)| connection_json(vhost, name, c))
+                .collect(),
+        )),
+        ("GET", "vhosts", ["channels"]) => {
+            let channels = v
+                .connections
+                .iter()
+                .map(|(name, c)| channel_json(vhost, name, c))
+                .collect();
+            list(channels, request)
+        }
+        ("GET", "consumers", []) => Response::ok(Value::Array(
+            v.connections
+                .iter()
+                .flat_map(|(name, c)| {
+                    c.consumed_queues.iter().map(move |queue| {
+                        json!({
+                            "consumer_tag": format!("ctag-{}", c.port),
+                            "active": true,
+                            "ack_required": true,
+                            "prefetch_count": 0,
+                            "exclusive": false,
+                            "arguments": {},
+                            "consumer_timeout": 1_800_000,
+                            "queue": {"name": queue, "vhost": vhost},
+                            "channel_details": {
+                                "number": 1,
+                                "name": format!("{} (1)", name),
+                                "connection_name": name,
+                                "node": "rabbit@fake",
+                                "peer_host": "127.0.0.1",
+                                "peer_port": c.port,
+                                "user": "guest"
+                            }
+                        })
+                    })
+                })
+                .collect(),
+        )),
+        _ => Response::not_found(),
+    }
+}
+
+fn policies_mut<'v>(v: &'v mut Vhost, kind: &str) -> &'v mut BTreeMap<String, Value> {
+    if kind == "policies" {
+        &mut v.policies
+    } else {
+        &mut v.operator_policies
+    }
+}
+
+fn consumer_count(v: &Vhost, queue: &str) -> usize {
+    v.connections
+        .values()
+        .filter(|c| c.consumed_queues.iter().any(|q| q == queue))
+        .count()
+}
+
+fn connection_json(vhost: &str, name: &str, c: &Connection) -> Value {
+    json!({
+        "name": name,
+        "vhost": vhost,
+        "node": "rabbit@fake",
+        "state": "running",
+        "protocol": "AMQP 0-9-1",
+        "user": "guest",
+        "connected_at": c.connected_at,
+        "host": "127.0.0.1",
+        "port": 5672,
+        "peer_host": "127.0.0.1",
+        "peer_port": c.port,
+        "channel_max": 2047,
+        "channels": 1,
+        "client_properties": {"connection_name": c.client_name}
+    })
+}
+
+/// Channels report since when they are idle only while they don't consume
+fn channel_json(vhost: &str, connection_name: &str, c: &Connection) -> Value {
+    let mut channel = json!({
+        "number": 1,
+        "name": format!("{} (1)", connection_name),
+        "connection_details": {
+            "name": connection_name,
+            "peer_host": "127.0.0.1",
+            "peer_port": c.port
+        },
+        "vhost": vhost,
+        "state": "running",
+        "consumer_count": c.consumed_queues.len(),
+        "confirm": false,
+        "prefetch_count": 0,
+        "messages_unacknowledged": 0,
+        "messages_unconfirmed": 0
+    });
+    if c.consumed_queues.is_empty() {
+        channel["state"] = json!("idle");
+        channel["idle_since"] = json!(
+            chrono::DateTime::from_timestamp_millis(c.connected_at as i64)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        );
+    }
+    channel
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn queue_json(vhost: &str, name: &str, q: &Queue, consumers: usize) -> Value {
+    let mut queue = json!({
+        "name": name,
+        "vhost": vhost,
+        "type": q.queue_type,
+        "durable": q.durable,
+        "auto_delete": q.auto_delete,
+        "exclusive": false,
+        "arguments": q.arguments,
+        "node": "rabbit@fake",
+        "state": "running",
+        "consumers": consumers,
+        "messages": q.messages.len(),
+        "messages_ready": q.messages.len(),
+        "messages_unacknowledged": 0
+    });
+    if q.queue_type == "quorum" {
+        // the node of a quorum queue is its leader
+        queue["node"] = json!(q.leader.as_deref().unwrap_or(QUORUM_MEMBERS[0]));
+        queue["leader"] = json!(q.leader);
+        queue["members"] = json!(QUORUM_MEMBERS);
+        queue["online"] = json!(q.online);
+    }
+    queue
+}
+
+/// Lists objects as a whole or, if a page is requested, page by page with the optional name filter.
+/// Like the management plugin, the filter is a case-insensitive regex with `use_regex=true` and a substring otherwise
+fn list(items: Vec<Value>, request: &Request) -> Response {
+    let Some(page) = request.query.get("page") else {
+        return Response::ok(Value::Array(items));
+    };
+    let Some(page) = page.parse::<usize>().ok().filter(|p| *p >= 1) else {
+        return Response::error(400, "page must be a positive number");
+    };
+    let page_size: usize = request
+        .query
+        .get("page_size")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(100);
+
+    let use_regex = request.query.get("use_regex").is_some_and(|u| u == "true");
+    let items: Vec<Value> = match request.query.get("name") {
+        Some(name) if use_regex => {
+            let Ok(filter) = RegexBuilder::new(name).case_insensitive(true).build() else {
+                return Response::error(400, "Invalid regex");
+            };
+            items
+                .into_iter()
+                .filter(|item| filter.is_match(item["name"].as_str().unwrap_or("")))
+                .collect()
+        }
+        Some(name) => {
+            let name = name.to_lowercase();
+            items
+                .into_iter()
+                .filter(|item| {
+                    item["name"]
+                        .as_str()
+                        .unwrap_or("")
+                        .to_lowercase()
+                        .contains(&name)
+                })
+                .collect()
+        }
+        None => items,
+    };
+
+    let page_count = items.len().div_ceil(page_size).max(1);
+    let page_items: Vec<Value> = items
+        .iter()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+        .cloned()
+        .collect();
+    Response::ok(json!({
+        "filtered_count": items.len(),
+        "item_count": page_items.len(),
+        "page": page,
+        "page_count": page_count,
+        "page_size": page_size,
+        "total_count": items.len(),
+        "items": page_items
+    }))
+}
+
+/// Delivers a message to queues bound directly or through other exchanges. Exchanges of type direct match
+/// the routing key exactly, all other types deliver to every binding
+fn route(v: &mut Vhost, message: &Message) -> bool {
+    let mut queues = vec![];
+    if message.exchange.is_empty() {
+        queues.push(message.routing_key.clone());
+    } else {
+        let mut exchanges = vec![message.exchange.clone()];
+        let mut visited = HashSet::new();
+        while let Some(exchange) = exchanges.pop() {
+            if !visited.insert(exchange.clone()) {
+                continue;
+            }
+            let direct = v
+                .exchanges
+                .get(&exchange)
+                .is_some_and(|x| x.exchange_type == "direct");
+            for binding in v.bindings.iter().filter(|b| b.source == exchange) {
+                if direct && binding.routing_key != message.routing_key {
+                    continue;
+                }
+                if binding.destination_type == "queue" {
+                    queues.push(binding.destination.clone());
+                } else {
+                    exchanges.push(binding.destination.clone());
+                }
+            }
+        }
+    }
+
+    let mut routed = false;
+    for queue in queues {
+        if let Some(q) = v.queues.get_mut(&queue) {
+            q.messages.push_back(message.clone());
+            routed = true;
+        }
+    }
+    routed
+}
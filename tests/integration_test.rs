@@ -1,8 +1,16 @@
 mod common;
 
-use crate::common::{create_args, wait_for_0_messages, TestClient};
-use clean_rmq::{Action, Args, DeleteOptions};
+use crate::common::{Fault, TestClient, create_args, wait_for_0_messages, wait_for_n_messages};
+use clean_rmq::{
+    Action, ApplyOptions, ApplyPolicyOptions, Args, Cleaner, ConnectionsOptions, DeleteOptions,
+    DeleteSelection, ListOptions, ListedObjects, ObjectKind, Outcome, PlanOptions, PurgeOptions,
+    QueueSelection, WatchOptions,
+};
+use rabbitmq_http_client::requests::MessageProperties;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub type TestingResult = Result<(), Box<dyn Error>>;
 
@@ -48,10 +56,7 @@ fn purge_in_dry_run_mode_doesnt_change_anything() -> TestingResult {
     client.publish_message_and_wait_delivery_in("e2", "with_message")?; // queue2 is not empty
 
     let args = Args {
-        action: Some(Action::Purge {
-            queue_filter: ".+".to_string(),
-            exclude_queue_filter: vec![],
-        }),
+        action: Some(Action::Purge(PurgeOptions::default())),
         ..create_args(&client, true)
     };
     clean_rmq::run(args)?;
@@ -83,10 +88,11 @@ fn purge_with_filter() -> TestingResult {
     setup("e3", "queue_special_error")?;
 
     let args = Args {
-        action: Some(Action::Purge {
+        action: Some(Action::Purge(PurgeOptions {
             queue_filter: ".+_error".to_string(),
             exclude_queue_filter: vec![".*special.*".to_string()],
-        }),
+            ..PurgeOptions::default()
+        })),
         ..create_args(&client, false)
     };
     clean_rmq::run(args)?;
@@ -99,6 +105,125 @@ fn purge_with_filter() -> TestingResult {
     Ok(())
 }
 
+#[test]
+fn purge_only_messages_matching_predicate() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_payload("e1", r#"{"id": 1}"#)?;
+    client.publish_payload("e1", r#"{"id": 2}"#)?;
+    client.publish_payload("e1", "not json")?;
+    wait_for_n_messages(&client, "q1", 3)?;
+
+    let audit_log = std::env::temp_dir().join(format!("{}.jsonl", client.vhost()));
+    let args = Args {
+        action: Some(Action::Purge(PurgeOptions {
+            message_filter: vec!["json.id=^2$".to_string()],
+            batch_size: 2,
+            ..PurgeOptions::default()
+        })),
+        audit_log: Some(audit_log.clone()),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let lines = std::fs::read_to_string(&audit_log)?;
+    std::fs::remove_file(audit_log)?;
+    let outcomes: Vec<String> = lines
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).map(|e| e["outcome"].to_string())
+        })
+        .collect::<Result<_, _>>()?;
+    assert_eq!(outcomes, [r#""attempted""#, r#""succeeded""#]);
+
+    wait_for_n_messages(&client, "q1", 2)?;
+    let messages = client.peek_messages("q1")?;
+    let payloads: Vec<&str> = messages.iter().map(|m| m.payload.as_str()).collect();
+    assert_eq!(payloads, [r#"{"id": 1}"#, "not json"]);
+    assert_eq!(
+        messages[0].properties.0["headers"]["x-original-exchange"],
+        "e1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn purge_only_messages_older_than() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    for timestamp in [now - 7200, now - 3700, now - 60, now - 7200] {
+        let mut properties = MessageProperties::new();
+        properties.insert("timestamp".to_string(), timestamp.into());
+        client.publish_payload_with_properties("e1", "data", properties)?;
+    }
+    wait_for_n_messages(&client, "q1", 4)?;
+
+    let args = Args {
+        action: Some(Action::Purge(PurgeOptions {
+            older_than: Some(Duration::from_secs(3600)),
+            ..PurgeOptions::default()
+        })),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    // the last message is old too, but it stays behind the younger one
+    wait_for_n_messages(&client, "q1", 2)?;
+
+    Ok(())
+}
+
+#[test]
+fn purge_rejects_too_large_age() -> TestingResult {
+    let client = TestClient::new()?;
+
+    let error =
+        common::run_cli(&client, &["purge", "--older-than", "300000000000000d"]).unwrap_err();
+    assert!(error.to_string().contains("is too large"), "{}", error);
+
+    Ok(())
+}
+
+#[test]
+fn selective_purge_rejects_empty_batches_and_revalidation() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+
+    let error = common::run_cli(
+        &client,
+        &["purge", "--older-than", "1s", "--batch-size", "0"],
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("--batch-size"), "{}", error);
+    let error =
+        common::run_cli(&client, &["purge", "--where", "payload=.", "--revalidate"]).unwrap_err();
+    assert!(
+        error.to_string().contains("cannot be used with"),
+        "{}",
+        error
+    );
+
+    // options of rules files aren't checked by the parser
+    let args = Args {
+        action: Some(Action::Purge(PurgeOptions {
+            message_filter: vec!["payload=.".to_string()],
+            batch_size: 0,
+            ..PurgeOptions::default()
+        })),
+        ..create_args(&client, false)
+    };
+    assert!(clean_rmq::run(args).is_err());
+    assert_eq!(client.get_number_of_messages("q1")?, 1);
+
+    Ok(())
+}
+
 #[test]
 fn delete_all_exchanges() -> TestingResult {
     let client = TestClient::new()?;
@@ -351,17 +476,1316 @@ fn delete_queues_with_filter() -> TestingResult {
     Ok(())
 }
 
-fn delete_action(f: fn(&mut DeleteOptions)) -> Option<Action> {
+#[test]
+fn peek_doesnt_consume_messages() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    let mut properties = MessageProperties::new();
+    properties.insert("content_type".to_string(), "application/json".into());
+    properties.insert("headers".to_string(), serde_json::json!({"tenant": "acme"}));
+    client.publish_payload_with_properties("e1", r#"{"id":42}"#, properties)?;
+    wait_for_n_messages(&client, "q1", 1)?;
+
+    let output = common::run_cli(
+        &client,
+        &["peek", "-f", "^q1$", "--json", "--max-payload-length", "4"],
+    )?;
+    let peeked: serde_json::Value = serde_json::from_str(&output)?;
+
+    assert_eq!(peeked[0]["queue"], "q1");
+    let message = &peeked[0]["messages"][0];
+    assert_eq!(message["exchange"], "e1");
+    assert_eq!(message["properties"]["content_type"], "application/json");
+    assert_eq!(message["headers"]["tenant"], "acme");
+    assert_eq!(message["payload_encoding"], "json");
+    assert_eq!(message["payload_bytes"], 9);
+    assert_eq!(message["payload"], r#"{"id"#);
+    assert_eq!(message["payload_truncated"], true);
+    assert_eq!(1, client.get_number_of_messages("q1")?);
+
+    Ok(())
+}
+
+#[test]
+fn peek_skips_streams() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.create_connected_stream("s1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+
+    let output = common::run_cli(&client, &["peek"])?;
+    assert!(output.contains("Skipping stream queue s1"), "{}", output);
+    assert!(
+        output.contains("Peeked into 1 queues, skipped 1 stream queues"),
+        "{}",
+        output
+    );
+
+    let output = common::run_cli(&client, &["peek", "--json"])?;
+    let peeked: serde_json::Value = serde_json::from_str(&output)?;
+    assert_eq!(peeked.as_array().map(Vec::len), Some(1));
+    assert_eq!(peeked[0]["queue"], "q1");
+
+    Ok(())
+}
+
+#[test]
+fn apply_and_remove_policy() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("temp_1")?;
+    client.create_queue("temp_2")?;
+    client.create_queue("other")?;
+    client.create_policy("clean_rmq-other", "^other$", 0)?;
+
+    let mut options = ApplyPolicyOptions {
+        queue_filter: "^temp_".to_string(),
+        exclude_queue_filter: vec!["_2$".to_string()],
+        name: None,
+        priority: 0,
+        message_ttl: Some(Duration::from_secs(60)),
+        expires: None,
+        max_length: Some(10),
+        overflow: None,
+        max_age: None,
+        max_length_bytes: None,
+        remove: false,
+    };
+    clean_rmq::run(Args {
+        action: Some(Action::ApplyPolicy(options.clone())),
+        ..create_args(&client, false)
+    })?;
+
+    let policies: Vec<_> = client
+        .list_policies()?
+        .into_iter()
+        .filter(|p| p.name != "clean_rmq-other")
+        .collect();
+    assert_eq!(1, policies.len());
+    assert_eq!("^(?!.*(?:_2$)).*(?:^temp_)", policies[0].pattern);
+    assert!(policies[0].name.starts_with("clean_rmq-"));
+
+    options.message_ttl = None;
+    options.max_length = None;
+    options.remove = true;
+    clean_rmq::run(Args {
+        action: Some(Action::ApplyPolicy(options)),
+        ..create_args(&client, false)
+    })?;
+
+    let policies = client.list_policies()?;
+    assert_eq!(1, policies.len());
+    assert_eq!("clean_rmq-other", policies[0].name);
+
+    Ok(())
+}
+
+#[test]
+fn purge_skips_streams_whose_retention_is_reduced_by_policy() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.create_connected_stream("s1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "s1")?;
+    wait_for_n_messages(&client, "q1", 1)?;
+
+    clean_rmq::run(create_args(&client, false))?;
+
+    wait_for_0_messages(&client, "q1")?;
+    assert_eq!(client.get_number_of_messages("s1")?, 1);
+
+    clean_rmq::run(Args {
+        action: Some(Action::ApplyPolicy(ApplyPolicyOptions {
+            queue_filter: "^s1$".to_string(),
+            max_age: Some(Duration::from_secs(12 * 3600)),
+            ..ApplyPolicyOptions::default()
+        })),
+        ..create_args(&client, false)
+    })?;
+
+    let policies = client.list_policies()?;
+    assert_eq!(1, policies.len());
+    let definition = policies[0].definition.0.clone().unwrap_or_default();
+    assert_eq!(definition["max-age"], "43200s");
+
+    Ok(())
+}
+
+#[test]
+fn delete_unused_policies() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("q1")?;
+    client.create_queue("q2")?;
+    client.create_policy("used", "^q1$", 0)?;
+    client.create_policy("unused", "^nothing$", 0)?;
+    client.create_policy("used_by_deleted_queue", "^q2$", 0)?;
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.queue_filter = "^q2$".to_string();
+            options.policies = true;
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let policies: Vec<_> = client
+        .list_policies()?
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    assert_eq!(vec!["used".to_string()], policies);
+
+    Ok(())
+}
+
+#[test]
+fn close_idle_connections() -> TestingResult {
+    // connections can't be opened through the management API
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_queue("q1")?;
+    let hour = Duration::from_secs(3600);
+    server.open_connection(client.vhost(), "consumer", &["q1"], 2 * hour);
+    server.open_connection(client.vhost(), "stale", &[], 2 * hour);
+    server.open_connection(client.vhost(), "recent", &[], Duration::from_secs(60));
+
+    let args = Args {
+        action: Some(Action::Connections(ConnectionsOptions {
+            user: Some("^guest$".to_string()),
+            idle: Some(hour),
+            close: true,
+            ..ConnectionsOptions::default()
+        })),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let mut connections = client.list_connections()?;
+    connections.sort();
+    assert_eq!(connections, ["consumer", "recent"]);
+
+    Ok(())
+}
+
+#[test]
+fn delete_checks_limits_before_closing_connections() -> TestingResult {
+    // consumers can't be started through the management API
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    for name in ["q0", "q1", "q2"] {
+        client.create_queue(name)?;
+    }
+    server.open_connection(client.vhost(), "test-runner", &["q0"], Duration::ZERO);
+    let delete = [
+        "delete",
+        "-q",
+        "--queues-without-consumers",
+        "--close-connections",
+        "^test-runner",
+    ];
+
+    // q0 loses its consumer with the connection, so it counts towards the limit too
+    let limited = [&["--yes", "--max-queues", "2"][..], &delete].concat();
+    assert!(common::run_cli(&client, &limited).is_err());
+    assert_eq!(client.list_connections()?, ["test-runner"]);
+    assert_eq!(client.list_queues()?.len(), 3);
+
+    let output = common::run_cli(&client, &[&["--yes"][..], &delete].concat())?;
+    assert!(output.contains("1 connections are closed before deleting:"));
+    assert!(client.list_connections()?.is_empty());
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn delete_queues_forcing_consumers() -> TestingResult {
+    // consumers can't be started through the management API
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_queue("one")?;
+    client.create_queue("other")?;
+    client.create_queue("idle")?;
+    server.open_connection(
+        client.vhost(),
+        "zombie",
+        &["one", "other"],
+        Duration::from_secs(60),
+    );
+
+    let output = common::run_cli(
+        &client,
+        &[
+            "--yes",
+            "delete",
+            "-q",
+            "--queues-without-consumers",
+            "--force-consumers",
+            "-f",
+            "^(one|idle)$",
+        ],
+    )?;
+
+    assert!(output.contains("zombie"), "{}", output);
+    assert!(
+        output.contains("cancels consumers on 1 queues that aren't deleted: other"),
+        "{}",
+        output
+    );
+    let mut queues = client.list_queues()?;
+    queues.sort();
+    assert_eq!(queues, ["other"]);
+    assert!(client.list_connections()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn watch_runs_rules_from_file() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q_error", "e1")?;
+    client.create_exchange("unbound")?;
+    client.publish_message_and_wait_delivery_in("e1", "q_error")?;
+
+    let rules = std::env::temp_dir().join(format!("{}.toml", client.vhost()));
+    std::fs::write(
+        &rules,
+        r#"
+[[rule]]
+name = "error queues"
+interval = "1h"
+
+[rule.purge]
+queue-filter = "_error$"
+
+[[rule]]
+name = "dangling exchanges"
+interval = "1h"
+
+[rule.delete]
+exchanges = true
+exchanges-without-destination = true
+"#,
+    )?;
+
+    let args = Args {
+        action: Some(Action::Watch(WatchOptions {
+            rules: rules.clone(),
+            max_cycles: 1,
+        })),
+        ..create_args(&client, false)
+    };
+    let result = clean_rmq::run(args);
+    std::fs::remove_file(rules)?;
+    result?;
+
+    wait_for_0_messages(&client, "q_error")?;
+    let exchanges = client.list_exchanges()?;
+    assert!(exchanges.contains(&"e1".to_string()));
+    assert!(!exchanges.contains(&"unbound".to_string()));
+
+    Ok(())
+}
+
+#[test]
+fn watch_rejects_rules_closing_connections() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("one")?;
+
+    let rules = std::env::temp_dir().join(format!("{}.toml", client.vhost()));
+    std::fs::write(
+        &rules,
+        r#"
+[[rule]]
+name = "zombie consumers"
+interval = "1h"
+
+[rule.delete]
+queues = true
+force-consumers = true
+"#,
+    )?;
+
+    let args = Args {
+        action: Some(Action::Watch(WatchOptions {
+            rules: rules.clone(),
+            max_cycles: 1,
+        })),
+        ..create_args(&client, false)
+    };
+    let result = clean_rmq::run(args);
+    std::fs::remove_file(rules)?;
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("allow-closing-connections"), "{}", error);
+    assert_eq!(client.list_queues()?, ["one"]);
+
+    Ok(())
+}
+
+#[test]
+fn watch_rejects_zero_interval() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("one")?;
+
+    let rules = std::env::temp_dir().join(format!("{}.toml", client.vhost()));
+    std::fs::write(
+        &rules,
+        r#"
+[[rule]]
+name = "busy loop"
+interval = "0s"
+
+[rule.purge]
+"#,
+    )?;
+
+    let args = Args {
+        action: Some(Action::Watch(WatchOptions {
+            rules: rules.clone(),
+            max_cycles: 1,
+        })),
+        ..create_args(&client, false)
+    };
+    let result = clean_rmq::run(args);
+    std::fs::remove_file(rules)?;
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("longer than 0s"), "{}", error);
+
+    Ok(())
+}
+
+#[test]
+fn apply_runs_steps_in_order() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("test_q", "e1")?;
+    client.create_queue("keep")?;
+    client.publish_message_and_wait_delivery_in("e1", "test_q")?;
+
+    let rules = std::env::temp_dir().join(format!("{}.toml", client.vhost()));
+    std::fs::write(
+        &rules,
+        r#"
+[[step]]
+name = "test queues"
+
+[step.delete]
+queues = true
+queue-filter = "^test_"
+
+[[step]]
+name = "dangling exchanges"
+
+[step.delete]
+exchanges = true
+exchanges-without-destination = true
+"#,
+    )?;
+
+    let args = Args {
+        action: Some(Action::Apply(ApplyOptions {
+            plan: None,
+            rules: Some(rules.clone()),
+        })),
+        ..create_args(&client, false)
+    };
+    let result = clean_rmq::run(args);
+    std::fs::remove_file(rules)?;
+    result?;
+
+    let queues = client.list_queues()?;
+    assert!(!queues.contains(&"test_q".to_string()));
+    assert!(queues.contains(&"keep".to_string()));
+    // the exchange lost its only queue in the first step
+    assert!(!client.list_exchanges()?.contains(&"e1".to_string()));
+
+    Ok(())
+}
+
+#[test]
+fn purge_writes_metrics_textfile() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+
+    let textfile = std::env::temp_dir().join(format!("{}.prom", client.vhost()));
+    let args = Args {
+        metrics_textfile: Some(textfile.clone()),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let metrics = std::fs::read_to_string(&textfile)?;
+    std::fs::remove_file(textfile)?;
+    let labels = format!("{{vhost=\"{}\",rule=\"purge\"}}", client.vhost());
+    assert!(metrics.contains(&format!("clean_rmq_messages_purged_total{} 1", labels)));
+    assert!(metrics.contains(&format!("clean_rmq_failures_total{} 0", labels)));
+
+    Ok(())
+}
+
+#[test]
+fn delete_writes_audit_log() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+
+    let audit_log = std::env::temp_dir().join(format!("{}.jsonl", client.vhost()));
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+        }),
+        audit_log: Some(audit_log.clone()),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let lines = std::fs::read_to_string(&audit_log)?;
+    std::fs::remove_file(audit_log)?;
+    let entries: Vec<serde_json::Value> = lines
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["object"], "q1");
+    assert_eq!(entries[0]["object_type"], "queue");
+    assert_eq!(entries[0]["messages"], 1);
+    assert_eq!(entries[0]["action"], "delete");
+    assert_eq!(entries[0]["outcome"], "attempted");
+    assert_eq!(entries[1]["object"], "q1");
+    assert_eq!(entries[1]["outcome"], "succeeded");
+
+    Ok(())
+}
+
+#[test]
+fn delete_queues_in_parallel() -> TestingResult {
+    let client = TestClient::new()?;
+    for i in 0..20 {
+        client.create_queue(&format!("q{}", i))?;
+    }
+    client.create_queue("keep")?;
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.queue_filter = "^q".to_string();
+            options.concurrency = 4;
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    assert_eq!(client.list_queues()?, vec!["keep".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn delete_respects_request_rate_limit() -> TestingResult {
+    let client = TestClient::new()?;
+    for i in 0..10 {
+        client.create_queue(&format!("q{}", i))?;
+    }
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.concurrency = 4;
+        }),
+        max_requests_per_second: Some(5),
+        ..create_args(&client, false)
+    };
+    let started = std::time::Instant::now();
+    clean_rmq::run(args)?;
+
+    // 1 listing and 10 deletions, the first one starts right away
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn delete_collects_queues_from_all_pages() -> TestingResult {
+    let client = TestClient::new()?;
+    // more than one page of 500 queues
+    for i in 0..510 {
+        client.create_queue(&format!("q{}", i))?;
+    }
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.queue_filter = "^q".to_string();
+            options.concurrency = 8;
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn delete_with_revalidation_deletes_unchanged_queues() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+    client.create_queue("q2")?;
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.revalidate = true;
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn delete_with_revalidation_skips_changed_queues() -> TestingResult {
+    // the queues are changed while the fake server delays deleting the first one
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_queue("q1")?;
+    client.create_connected_queue("q2", "e1")?;
+    client.create_queue("q3")?;
+    server.inject(Fault::delay(
+        "DELETE",
+        &format!("queues/{}/q1", client.vhost()),
+        Duration::from_secs(1),
+        1,
+    ));
+
+    let output = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(500));
+            client.publish_payload("e1", "new").unwrap();
+            server.open_connection(client.vhost(), "consumer", &["q3"], Duration::ZERO);
+        });
+        common::run_cli(
+            &client,
+            &["delete", "-q", "--revalidate", "--concurrency", "1"],
+        )
+    })?;
+
+    assert!(output.contains("⏭️  Skipping changed queue q2 - messages increased from 0 to 1"));
+    assert!(output.contains("⏭️  Skipping changed queue q3 - 1 consumers appeared"));
+    assert!(output.contains("Skipped 2 queues that changed since collection"));
+    assert_eq!(client.list_queues()?, vec!["q2", "q3"]);
+
+    Ok(())
+}
+
+#[test]
+fn delete_refuses_to_exceed_limits_without_force() -> TestingResult {
+    let client = TestClient::new()?;
+    for i in 0..3 {
+        client.create_queue(&format!("q{}", i))?;
+    }
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+        }),
+        max_queues: Some(2),
+        ..create_args(&client, false)
+    };
+    assert!(clean_rmq::run(args).is_err());
+    assert_eq!(client.list_queues()?.len(), 3);
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+        }),
+        max_queues: Some(2),
+        force: true,
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn purge_on_protected_cluster_needs_filter_and_cluster_name() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+    client.create_queue("q2")?;
+
+    let profiles = std::env::temp_dir().join(format!("{}-profiles.toml", client.vhost()));
+    std::fs::write(
+        &profiles,
+        format!("protected-clusters = ['^{}$']", client.cluster_name()?),
+    )?;
+    let protected_args = |queue_filter: &str, confirm_cluster: Option<String>| Args {
+        profiles_file: Some(profiles.clone()),
+        confirm_cluster,
+        action: Some(Action::Purge(PurgeOptions {
+            queue_filter: queue_filter.to_string(),
+            ..PurgeOptions::default()
+        })),
+        ..create_args(&client, false)
+    };
+
+    // filters that match anything and filters that match every queue of the vhost are refused
+    for filter in [".+", "(?s).", "q*", "^q"] {
+        assert!(clean_rmq::run(protected_args(filter, Some(client.cluster_name()?))).is_err());
+    }
+    assert!(clean_rmq::run(protected_args("^q1$", Some("other".to_string()))).is_err());
+    assert_eq!(client.get_number_of_messages("q1")?, 1);
+
+    clean_rmq::run(protected_args("^q1$", Some(client.cluster_name()?)))?;
+    wait_for_0_messages(&client, "q1")?;
+
+    std::fs::remove_file(&profiles)?;
+    Ok(())
+}
+
+#[test]
+fn default_filter_is_refused_on_protected_vhost_with_one_queue() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("q1", "e1")?;
+    client.publish_message_and_wait_delivery_in("e1", "q1")?;
+
+    let profiles = std::env::temp_dir().join(format!("{}-profiles.toml", client.vhost()));
+    std::fs::write(
+        &profiles,
+        format!("protected-clusters = ['^{}$']", client.cluster_name()?),
+    )?;
+    let protected_args = |action: Action| Args {
+        profiles_file: Some(profiles.clone()),
+        confirm_cluster: Some(client.cluster_name().unwrap()),
+        action: Some(action),
+        ..create_args(&client, false)
+    };
+
+    assert!(clean_rmq::run(protected_args(Action::Purge(PurgeOptions::default()))).is_err());
+    let delete = delete_options(|options| {
+        options.queues = true;
+        options.queue_filter = ".+".to_string();
+    });
+    assert!(clean_rmq::run(protected_args(Action::Delete(delete))).is_err());
+    assert_eq!(client.get_number_of_messages("q1")?, 1);
+    assert_eq!(client.list_queues()?, vec!["q1"]);
+
+    std::fs::remove_file(&profiles)?;
+    Ok(())
+}
+
+#[test]
+fn apply_plan_skips_queues_changed_since_planning() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("test_changed", "e1")?;
+    client.create_queue("test_unchanged")?;
+    client.create_queue("other")?;
+
+    let plan = std::env::temp_dir().join(format!("{}-plan.json", client.vhost()));
+    let args = Args {
+        action: Some(Action::Plan(PlanOptions {
+            out: plan.clone(),
+            delete: delete_options(|options| {
+                options.queues = true;
+                options.queue_filter = "^test_".to_string();
+            }),
+        })),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    assert_eq!(client.list_queues()?.len(), 3);
+
+    client.publish_message_and_wait_delivery_in("e1", "test_changed")?;
+
+    let args = Args {
+        action: Some(Action::Apply(ApplyOptions {
+            plan: Some(plan.clone()),
+            rules: None,
+        })),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    let queues = client.list_queues()?;
+    assert_eq!(queues.len(), 2);
+    assert!(queues.contains(&"test_changed".to_string()));
+    assert!(queues.contains(&"other".to_string()));
+
+    std::fs::remove_file(&plan)?;
+    Ok(())
+}
+
+#[test]
+fn cleaner_plans_and_deletes_queues() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("test_1")?;
+    client.create_queue("test_keep")?;
+    client.create_queue("other")?;
+
+    let endpoint = common::endpoint();
+    let progress = RefCell::new(vec![]);
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .on_progress(|object| progress.borrow_mut().push(object.name.clone()))
+        .build()?;
+
+    let plan = cleaner.plan_delete(
+        &DeleteSelection::new().queues(QueueSelection::matching("^test_").exclude("_keep$")),
+    )?;
+    assert_eq!(plan.queues.len(), 1);
+    assert_eq!(plan.queues[0].name, "test_1");
+
+    let result = cleaner.delete(&plan)?;
+    assert_eq!(result.done(ObjectKind::Queue), 1);
+    assert_eq!(*progress.borrow(), vec!["test_1".to_string()]);
+    assert_eq!(client.list_queues()?.len(), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_cleaner_plans_and_deletes_queues() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("test_1")?;
+    client.create_queue("test_2")?;
+    client.create_queue("test_keep")?;
+
+    let endpoint = common::endpoint();
+    let textfile = std::env::temp_dir().join(format!("{}.prom", client.vhost()));
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .concurrency(2)
+        .metrics_textfile(&textfile)
+        .build_async()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(spawnable(async {
+        let plan = cleaner
+            .plan_delete(
+                &DeleteSelection::new()
+                    .queues(QueueSelection::matching("^test_").exclude("_keep$")),
+            )
+            .await?;
+        cleaner.delete(&plan).await
+    }))?;
+
+    assert_eq!(result.done(ObjectKind::Queue), 2);
+    assert_eq!(client.list_queues()?, vec!["test_keep".to_string()]);
+
+    let metrics = std::fs::read_to_string(&textfile)?;
+    std::fs::remove_file(textfile)?;
+    let labels = format!("{{vhost=\"{}\",rule=\"delete\"}}", client.vhost());
+    assert!(metrics.contains(&format!("clean_rmq_queues_deleted_total{} 2", labels)));
+    assert!(metrics.contains(&format!("clean_rmq_failures_total{} 0", labels)));
+
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn cleaner_deletes_from_fake_broker() -> TestingResult {
+    use clean_rmq::FakeBroker;
+
+    let broker = FakeBroker::new();
+    broker.add_exchange("test", "unused");
+    broker.add_exchange("test", "used");
+    broker.add_queue(
+        "test",
+        clean_rmq::Queue {
+            name: "q1".to_string(),
+            ..clean_rmq::Queue::default()
+        },
+    );
+    broker.bind(
+        "test",
+        "used",
+        "q1",
+        rabbitmq_http_client::commons::BindingDestinationType::Queue,
+    );
+
+    let cleaner = Cleaner::builder("", "", "")
+        .vhost("test")
+        .build_with(broker)?;
+    let plan = cleaner.plan_delete(&DeleteSelection::new().exchanges_without_destination())?;
+    let result = cleaner.delete(&plan)?;
+
+    assert_eq!(result.done(ObjectKind::Exchange), 1);
+    assert_eq!(result.objects[0].name, "unused");
+
+    Ok(())
+}
+
+/// Futures of the async cleaner have to be Send to be spawned on a multi-threaded runtime
+#[cfg(feature = "async")]
+fn spawnable<F: std::future::Future + Send>(future: F) -> F {
+    future
+}
+
+#[test]
+fn delete_retries_transient_failures() -> TestingResult {
+    // faults can only be injected into the fake server
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_queue("q1")?;
+    client.create_queue("q2")?;
+    let queues = format!("queues/{}/", client.vhost());
+    server.inject(Fault::status("GET", &queues, 503, 1));
+    server.inject(Fault::status("DELETE", &queues, 503, 2));
+    server.inject(Fault::delay("DELETE", &queues, Duration::from_secs(2), 1));
+
+    let args = Args {
+        action: delete_action(|options| options.queues = true),
+        request_timeout: Duration::from_millis(500),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn parallel_delete_reports_queues_in_flight_after_a_failure() -> TestingResult {
+    // faults can only be injected into the fake server
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    let names: Vec<String> = (0..20).map(|i| format!("q{:02}", i)).collect();
+    for name in &names {
+        client.create_queue(name)?;
+    }
+    let queues = format!("queues/{}/", client.vhost());
+    server.inject(Fault::status("DELETE", &format!("{}q05", queues), 403, 1));
+    server.inject(Fault::delay(
+        "DELETE",
+        &queues,
+        Duration::from_millis(200),
+        20,
+    ));
+
+    let endpoint = common::endpoint();
+    let progress = std::sync::Mutex::new(vec![]);
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .concurrency(4)
+        .on_progress(|object| progress.lock().unwrap().push(object.name.clone()))
+        .build()?;
+    let plan = cleaner.plan_delete(&DeleteSelection::new().queues(QueueSelection::matching("")))?;
+    let result = cleaner.delete(&plan)?;
+    assert_eq!(result.failed(), 1);
+
+    // every queue that was touched is reported: the deleted ones and the failed one
+    let remaining = client.list_queues()?;
+    let touched: Vec<&String> = names
+        .iter()
+        .filter(|name| !remaining.contains(name) || *name == "q05")
+        .collect();
+    drop(cleaner);
+    let reported = progress.into_inner().unwrap();
+    assert_eq!(reported.iter().collect::<Vec<_>>(), touched);
+    let recorded: Vec<_> = result.objects.iter().map(|object| &object.name).collect();
+    assert_eq!(recorded, touched);
+    assert!(remaining.len() > 1, "deletion didn't stop: {:?}", remaining);
+
+    Ok(())
+}
+
+#[test]
+fn cleaner_keeps_going_after_a_failure() -> TestingResult {
+    // faults can only be injected into the fake server
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    for name in ["q1", "q2", "q3"] {
+        client.create_queue(name)?;
+    }
+    server.inject(Fault::status(
+        "DELETE",
+        &format!("queues/{}/q2", client.vhost()),
+        403,
+        1,
+    ));
+
+    let endpoint = common::endpoint();
+    let textfile = std::env::temp_dir().join(format!("{}.prom", client.vhost()));
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .keep_going(true)
+        .metrics_textfile(&textfile)
+        .build()?;
+    let plan = cleaner.plan_delete(&DeleteSelection::new().queues(QueueSelection::default()))?;
+    let result = cleaner.delete(&plan)?;
+
+    assert_eq!(result.done(ObjectKind::Queue), 2);
+    assert_eq!(result.failed(), 1);
+    assert!(matches!(&result.objects[1].outcome, Outcome::Failed(e) if e.contains("403")));
+    assert_eq!(client.list_queues()?, vec!["q2"]);
+
+    let metrics = std::fs::read_to_string(&textfile)?;
+    std::fs::remove_file(textfile)?;
+    let labels = format!("{{vhost=\"{}\",rule=\"delete\"}}", client.vhost());
+    assert!(metrics.contains(&format!("clean_rmq_queues_deleted_total{} 2", labels)));
+    assert!(metrics.contains(&format!("clean_rmq_failures_total{} 1", labels)));
+
+    Ok(())
+}
+
+#[test]
+fn cleaner_refuses_to_exceed_limits() -> TestingResult {
+    let client = TestClient::new()?;
+    client.create_queue("q1")?;
+    client.create_queue("q2")?;
+
+    let endpoint = common::endpoint();
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .max_queues(1)
+        .build()?;
+    let plan = cleaner.plan_delete(&DeleteSelection::new().queues(QueueSelection::default()))?;
+    assert!(cleaner.delete(&plan).is_err());
+    assert_eq!(client.list_queues()?.len(), 2);
+
+    // in dry run mode exceeded limits are only reported, like on the command line
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .max_queues(1)
+        .dry_run(true)
+        .build()?;
+    let result = cleaner.delete(&plan)?;
+    assert_eq!(result.done(ObjectKind::Queue), 2);
+    assert_eq!(client.list_queues()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn fake_server_filters_and_pages_like_the_management_plugin() -> TestingResult {
+    let Some(_server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    for name in ["Test_1", "test_2", "other.test"] {
+        client.create_queue(name)?;
+    }
+
+    let http = reqwest::blocking::Client::new();
+    let list = |query: &str| -> Result<reqwest::blocking::Response, reqwest::Error> {
+        let url = format!("{}/queues/{}?{}", common::endpoint(), client.vhost(), query);
+        http.get(url).basic_auth("guest", Some("guest")).send()
+    };
+    let names = |query: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let page: serde_json::Value = list(query)?.error_for_status()?.json()?;
+        Ok(page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|q| q["name"].as_str().unwrap().to_string())
+            .collect())
+    };
+
+    assert_eq!(names("page=1&name=.test")?, vec!["other.test"]);
+    assert_eq!(
+        names("page=1&name=^test_&use_regex=true")?,
+        vec!["Test_1", "test_2"]
+    );
+    assert_eq!(list("page=0")?.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn delete_skips_unhealthy_quorum_queues() -> TestingResult {
+    // a real single node broker can't lose quorum on demand
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_quorum_queue("healthy")?;
+    client.create_quorum_queue("electing")?;
+    client.create_quorum_queue("degraded")?;
+    server.set_quorum_state(
+        client.vhost(),
+        "electing",
+        None,
+        &["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"],
+    );
+    server.set_quorum_state(
+        client.vhost(),
+        "degraded",
+        Some("rabbit@fake"),
+        &["rabbit@fake"],
+    );
+
+    // the election ends while waiting, the offline members don't come back
+    let vhost = client.vhost().to_string();
+    let election = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        server.set_quorum_state(
+            &vhost,
+            "electing",
+            Some("rabbit@fake-2"),
+            &["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"],
+        );
+    });
+    let args = Args {
+        action: delete_action(|options| options.queues = true),
+        wait_for_quorum: Duration::from_secs(2),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    election.join().unwrap();
+    assert_eq!(client.list_queues()?, vec!["degraded"]);
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.quorum_unhealthy = true;
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    assert!(client.list_queues()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn unhealthy_quorum_queues_share_the_wait() -> TestingResult {
+    // a real single node broker can't lose quorum on demand
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    for name in ["q1", "q2", "q3"] {
+        client.create_quorum_queue(name)?;
+        server.set_quorum_state(client.vhost(), name, None, &["rabbit@fake"]);
+    }
+
+    let endpoint = common::endpoint();
+    let cleaner = Cleaner::builder(&endpoint, "guest", "guest")
+        .vhost(client.vhost())
+        .wait_for_quorum(Duration::from_secs(1))
+        .build()?;
+    let plan = cleaner.plan_delete(&DeleteSelection::new().queues(QueueSelection::default()))?;
+    let started = std::time::Instant::now();
+    let result = cleaner.delete(&plan)?;
+
+    assert_eq!(result.skipped_unhealthy(), 3);
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "waited {:?}",
+        started.elapsed()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn delete_selects_queues_by_node() -> TestingResult {
+    // a real single node broker hosts all queues on the same node
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_queue("classic")?;
+    client.create_quorum_queue("led-by-1")?;
+    client.create_quorum_queue("led-by-3")?;
+    let all_nodes = ["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"];
+    server.set_quorum_state(
+        client.vhost(),
+        "led-by-3",
+        Some("rabbit@fake-3"),
+        &all_nodes,
+    );
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.node = Some("@fake-3$".to_string());
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    assert_eq!(client.list_queues()?, vec!["classic", "led-by-1"]);
+
+    let args = Args {
+        action: delete_action(|options| {
+            options.queues = true;
+            options.replica_node = Some("@fake-2$".to_string());
+        }),
+        ..create_args(&client, false)
+    };
+    clean_rmq::run(args)?;
+    assert_eq!(client.list_queues()?, vec!["classic"]);
+
+    Ok(())
+}
+
+#[test]
+fn purge_selects_queues_by_node() -> TestingResult {
+    // a real single node broker hosts all queues on the same node
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_exchange("e1")?;
+    client.create_connected_queue("classic", "e1")?;
+    client.create_connected_quorum_queue("led-by-1", "e1")?;
+    client.create_connected_quorum_queue("led-by-3", "e1")?;
+    client.publish_payload("e1", "message")?;
+    let all_nodes = ["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"];
+    server.set_quorum_state(
+        client.vhost(),
+        "led-by-3",
+        Some("rabbit@fake-3"),
+        &all_nodes,
+    );
+
+    let output = common::run_cli(&client, &["purge", "--node", "@fake-3$"])?;
+    assert!(output.contains("led-by-3"), "{}", output);
+    assert_eq!(client.get_number_of_messages("led-by-3")?, 0);
+    assert_eq!(client.get_number_of_messages("led-by-1")?, 1);
+    assert_eq!(client.get_number_of_messages("classic")?, 1);
+
+    // classic queues have their only replica on their node
+    let output = common::run_cli(&client, &["purge", "--replica-node", "@fake-2$"])?;
+    assert!(output.contains("led-by-1"), "{}", output);
+    assert_eq!(client.get_number_of_messages("led-by-1")?, 0);
+    assert_eq!(client.get_number_of_messages("classic")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn list_nodes() -> TestingResult {
+    // a real single node broker can't refer to nodes outside of the cluster
+    let Some(server) = common::fake_server() else {
+        return Ok(());
+    };
+    let client = TestClient::new()?;
+    client.create_queue("classic")?;
+    client.create_quorum_queue("led-by-1")?;
+    client.create_quorum_queue("led-by-removed")?;
+    server.set_quorum_state(
+        client.vhost(),
+        "led-by-removed",
+        Some("rabbit@removed"),
+        &["rabbit@fake", "rabbit@fake-2", "rabbit@fake-3"],
+    );
+
+    let output = common::run_cli(&client, &["list", "nodes"])?;
+    let nodes: Vec<&str> = output
+        .lines()
+        .filter(|line| line.contains("Node "))
+        .collect();
+    assert_eq!(nodes.len(), 4, "{}", output);
+    assert!(nodes[0].starts_with("🖥️  Node rabbit@fake - running for "));
+    assert!(
+        nodes[0].ends_with("hosts 2 queues, 3 replicas"),
+        "{}",
+        output
+    );
+    assert!(
+        nodes[1].ends_with("hosts 0 queues, 2 replicas"),
+        "{}",
+        output
+    );
+    assert!(
+        nodes[2].ends_with("hosts 0 queues, 2 replicas"),
+        "{}",
+        output
+    );
+    assert_eq!(
+        nodes[3],
+        "🖥️  Node rabbit@removed - not in the cluster, hosts 1 queues, 0 replicas"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn list_nodes_respects_request_rate_limit() -> TestingResult {
+    let client = TestClient::new()?;
+
+    let args = Args {
+        action: Some(Action::List(ListOptions {
+            objects: ListedObjects::Nodes,
+        })),
+        max_requests_per_second: Some(1),
+        ..create_args(&client, false)
+    };
+    let started = std::time::Instant::now();
+    clean_rmq::run(args)?;
+
+    // the nodes and the queues are listed, the first request starts right away
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    Ok(())
+}
+
+fn delete_action(f: fn(&mut DeleteOptions)) -> Option<Action> {
+    Some(Action::Delete(delete_options(f)))
+}
+
+fn delete_options(f: fn(&mut DeleteOptions)) -> DeleteOptions {
     let mut options = DeleteOptions {
         queues: false,
         queues_without_consumers: false,
+        force_consumers: false,
+        quorum_unhealthy: false,
         queue_filter: "".to_string(),
         exclude_queue_filter: vec![],
+        exclude_queues: HashSet::new(),
+        node: None,
+        replica_node: None,
         exchanges: false,
         exchanges_without_destination: false,
+        policies: false,
+        operator_policies: false,
+        close_connections: None,
+        concurrency: 1,
+        revalidate: false,
+        max_message_increase: 0,
     };
     f(&mut options);
-    Some(Action::Delete(options))
+    options
 }
 
 /// create this topology: